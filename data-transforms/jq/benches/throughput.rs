@@ -0,0 +1,206 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Measures how many records per second the transform processes natively, and how many heap
+//! allocations it makes for each record, for a few typical configurations.
+//!
+//! Run with `cargo bench`, optionally with the name of a scenario to run only that one:
+//!
+//! ```text
+//! cargo bench -- delete
+//! ```
+//!
+//! Records are written to a sink that discards them, so this measures decoding, running the filter
+//! and encoding, but not the cost of crossing into the Wasm runtime.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::Result;
+use jq::config::Config;
+use jq::Transform;
+use redpanda_transform_sdk::{
+    BorrowedRecord, RecordSink, RecordWriter, WriteError, WriteEvent, WriteOptions, WrittenRecord,
+};
+
+/// Counts allocations so that they can be reported for each record.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// How long each scenario runs for, after warming up.
+const DURATION: Duration = Duration::from_secs(2);
+
+/// Discards written records, keeping only their total size.
+#[derive(Default)]
+struct NullSink {
+    bytes: usize,
+}
+
+impl RecordSink for NullSink {
+    fn write(&mut self, r: BorrowedRecord<'_>, _: WriteOptions<'_>) -> Result<(), WriteError> {
+        self.bytes += r.value().map_or(0, <[u8]>::len);
+        Ok(())
+    }
+}
+
+struct Scenario {
+    name: &'static str,
+    vars: &'static [(&'static str, &'static str)],
+}
+
+const SCENARIOS: &[Scenario] = &[
+    Scenario {
+        name: "identity",
+        vars: &[("FILTER", ".")],
+    },
+    Scenario {
+        name: "delete",
+        vars: &[("FILTER", "del(.customer.email)")],
+    },
+    Scenario {
+        name: "select",
+        vars: &[("FILTER", r#"select(.status == "shipped") | {id, total}"#)],
+    },
+    Scenario {
+        name: "envelope",
+        vars: &[
+            ("FILTER", ".key = .value.customer.id"),
+            ("ENVELOPE", "true"),
+        ],
+    },
+    Scenario {
+        name: "precise",
+        vars: &[
+            ("FILTER", "del(.customer.email)"),
+            ("PRESERVE_PRECISION", "true"),
+        ],
+    },
+    Scenario {
+        name: "paths",
+        vars: &[("FILTER", "[paths] | length")],
+    },
+    Scenario {
+        name: "paths-steps",
+        vars: &[("FILTER", "[paths] | length"), ("MAX_STEPS", "1000000")],
+    },
+    Scenario {
+        name: "json-stream",
+        vars: &[("FILTER", ".id"), ("INPUT_FORMAT", "json-stream")],
+    },
+];
+
+fn main() -> Result<()> {
+    // `cargo bench` passes `--bench`, anything else selects scenarios by name.
+    let only: Vec<String> = std::env::args()
+        .skip(1)
+        .filter(|a| !a.starts_with("--"))
+        .collect();
+    let values: Vec<Vec<u8>> = (0..1000).map(order).collect();
+    println!(
+        "{:<12} {:>14} {:>10} {:>18}",
+        "scenario", "records/sec", "MB/s", "allocations/record"
+    );
+    for scenario in SCENARIOS {
+        if !only.is_empty() && !only.iter().any(|o| scenario.name.contains(o.as_str())) {
+            continue;
+        }
+        let vars = scenario
+            .vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()));
+        let transform = Transform::new(Config::from_vars(vars)?)?;
+        run(&transform, &values, Duration::from_millis(200))?;
+        let result = run(&transform, &values, DURATION)?;
+        let secs = result.elapsed.as_secs_f64();
+        println!(
+            "{:<12} {:>14.0} {:>10.1} {:>18.1}",
+            scenario.name,
+            result.records as f64 / secs,
+            result.bytes as f64 / secs / 1e6,
+            result.allocations as f64 / result.records as f64,
+        );
+    }
+    Ok(())
+}
+
+struct Run {
+    records: usize,
+    /// The size of the values read.
+    bytes: usize,
+    allocations: usize,
+    elapsed: Duration,
+}
+
+/// Run the transform on the values in turn, until `duration` has passed.
+fn run(transform: &Transform, values: &[Vec<u8>], duration: Duration) -> Result<Run> {
+    let mut sink = NullSink::default();
+    let mut writer = RecordWriter::new(&mut sink);
+    let mut result = Run {
+        records: 0,
+        bytes: 0,
+        allocations: 0,
+        elapsed: Duration::ZERO,
+    };
+    let timestamp = SystemTime::now();
+    let start = Instant::now();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    while start.elapsed() < duration {
+        for value in values {
+            let event = WriteEvent {
+                record: WrittenRecord::new(Some(b"order-key"), Some(value), timestamp),
+            };
+            transform.apply(event, &mut writer)?;
+            result.bytes += value.len();
+        }
+        result.records += values.len();
+    }
+    result.elapsed = start.elapsed();
+    result.allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    Ok(result)
+}
+
+/// An order event of a few hundred bytes, like those of a typical JSON topic.
+fn order(n: usize) -> Vec<u8> {
+    let status = ["pending", "shipped", "delivered"][n % 3];
+    format!(
+        r#"{{"id":{n},"status":"{status}","created_at":"2024-02-12T14:58:41.390Z","customer":{{"id":"customer-{}","name":"Ada Lovelace","email":"ada@example.com"}},"items":[{{"sku":"sku-{}","quantity":{},"price":19.99}},{{"sku":"sku-{}","quantity":1,"price":5.25}}],"total":{}.23,"account_id":9007199254740993}}"#,
+        n % 97,
+        n % 13,
+        n % 5 + 1,
+        n % 17,
+        n % 400,
+    )
+    .into_bytes()
+}
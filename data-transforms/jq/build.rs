@@ -0,0 +1,163 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Embeds filters in the transform at build time, so that they're validated by the build instead
+//! of when the transform is deployed.
+//!
+//! - `EMBED_FILTER` is the path of a file holding the filter to embed.
+//! - `EMBED_FILTER_DEFS` is a comma separated list of paths of files holding jq definitions to
+//!   embed, which are loaded in order before any definitions passed at runtime.
+//! - `EMBED_FILTER_ARGS` is a comma separated list of the named arguments the filter uses, which
+//!   must be passed to the transform at runtime.
+//! - `EMBED_DESCRIPTOR_SET` is the path of a serialized `FileDescriptorSet` holding the message
+//!   types of the protobuf formats, such as one written by `protoc --include_imports -o`.
+//! - `EMBED_INPUT_SCHEMA` and `EMBED_OUTPUT_SCHEMA` are the paths of files holding the JSON Schemas
+//!   that inputs and outputs of the filter are validated with.
+//!
+//! The build fails with a report of each error if the filter or definitions are invalid, if the
+//! descriptor set cannot be decoded and if the schemas are invalid.
+
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use prost_reflect::DescriptorPool;
+
+#[path = "src/compile.rs"]
+mod compile;
+#[path = "src/diagnostic.rs"]
+mod diagnostic;
+#[path = "src/prelude.rs"]
+mod prelude;
+
+/// The build only compiles filters, so it compiles them against natives with the names of the
+/// transform's that are never run, instead of depending on everything the transform's need.
+mod builtins {
+    use jaq_interpret::results::box_once;
+    use jaq_interpret::Native;
+
+    use crate::prelude;
+
+    pub fn natives() -> impl Iterator<Item = (String, usize, Native)> {
+        let ours = prelude::NATIVES.iter().map(|&(name, arity)| {
+            let native = Native::new(|_, cv| box_once(Ok(cv.1)));
+            (name.to_owned(), arity, native)
+        });
+        prelude::with_core(ours)
+    }
+}
+
+fn main() -> Result<()> {
+    for var in [
+        "EMBED_FILTER",
+        "EMBED_FILTER_DEFS",
+        "EMBED_FILTER_ARGS",
+        "EMBED_DESCRIPTOR_SET",
+        "EMBED_INPUT_SCHEMA",
+        "EMBED_OUTPUT_SCHEMA",
+    ] {
+        println!("cargo:rerun-if-env-changed={var}");
+    }
+    for path in [
+        "build.rs",
+        "src/compile.rs",
+        "src/diagnostic.rs",
+        "src/prelude.rs",
+    ] {
+        println!("cargo:rerun-if-changed={path}");
+    }
+    let modules = list("EMBED_FILTER_DEFS")
+        .into_iter()
+        .map(read)
+        .collect::<Result<Vec<_>>>()?;
+    let filter = env::var("EMBED_FILTER").ok().map(read).transpose()?;
+    let args = list("EMBED_FILTER_ARGS");
+    let names = compile::var_names(args.iter().map(String::as_str))?;
+    match &filter {
+        // Precise mode only changes how `del` is defined, which doesn't change what is valid.
+        Some((name, source)) => compile::compile(&names, &modules, (name, source), false, false)?,
+        None => compile::compile(&names, &modules, ("FILTER", "."), false, false)?,
+    };
+    let descriptor_set = env::var("EMBED_DESCRIPTOR_SET")
+        .ok()
+        .map(descriptor_set)
+        .transpose()?;
+    let input_schema = schema("EMBED_INPUT_SCHEMA")?;
+    let output_schema = schema("EMBED_OUTPUT_SCHEMA")?;
+
+    let mut out = String::new();
+    writeln!(out, "pub const FILTER: Option<(&str, &str)> = {filter:?};")?;
+    writeln!(out, "pub const MODULES: &[(&str, &str)] = &{modules:?};")?;
+    writeln!(out, "pub const ARGS: &[&str] = &{args:?};")?;
+    match descriptor_set {
+        Some(path) => writeln!(
+            out,
+            "pub const DESCRIPTOR_SET: Option<&[u8]> = Some(include_bytes!({path:?}));"
+        )?,
+        None => writeln!(out, "pub const DESCRIPTOR_SET: Option<&[u8]> = None;")?,
+    }
+    writeln!(
+        out,
+        "pub const INPUT_SCHEMA: Option<(&str, &str)> = {input_schema:?};"
+    )?;
+    writeln!(
+        out,
+        "pub const OUTPUT_SCHEMA: Option<(&str, &str)> = {output_schema:?};"
+    )?;
+    let path = Path::new(&env::var("OUT_DIR")?).join("embedded.rs");
+    fs::write(path, out)?;
+    Ok(())
+}
+
+fn list(var: &str) -> Vec<String> {
+    env::var(var)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Read a file to embed, returning its path and contents.
+fn read(path: String) -> Result<(String, String)> {
+    println!("cargo:rerun-if-changed={path}");
+    let source = fs::read_to_string(&path).with_context(|| format!("cannot read {path}"))?;
+    Ok((path, source))
+}
+
+/// Validate a descriptor set to embed, returning its absolute path for `include_bytes!`.
+fn descriptor_set(path: String) -> Result<String> {
+    println!("cargo:rerun-if-changed={path}");
+    let set = fs::read(&path).with_context(|| format!("cannot read {path}"))?;
+    DescriptorPool::decode(set.as_slice())
+        .with_context(|| format!("{path} is not a valid descriptor set"))?;
+    let path = fs::canonicalize(&path).with_context(|| format!("cannot resolve {path}"))?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Read and validate the JSON Schema in the file named by `var`, returning its path and contents.
+fn schema(var: &str) -> Result<Option<(String, String)>> {
+    let Some((path, source)) = env::var(var).ok().map(read).transpose()? else {
+        return Ok(None);
+    };
+    let schema: serde_json::Value = serde_json::from_str(&source)
+        .with_context(|| format!("{path} is not a valid JSON Schema: schema is not valid JSON"))?;
+    jsonschema::draft202012::new(&schema)
+        .map_err(|e| anyhow!("{e}"))
+        .with_context(|| format!("{path} is not a valid JSON Schema"))?;
+    Ok(Some((path, source)))
+}
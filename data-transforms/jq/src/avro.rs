@@ -0,0 +1,493 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Avro values in the schema registry's wire format: a zero magic byte, the ID of the schema the
+//! value was written with as a big-endian 32-bit integer, and then the value itself.
+//!
+//! Values are read into JSON much like Avro's own JSON encoding, except that unions are unwrapped
+//! to the value of their branch:
+//!
+//! * records and maps are objects, and enums are the name of their symbol;
+//! * `bytes` and `fixed` are binary strings, represented like those of the `msgpack` and `cbor`
+//!   formats (see [`crate::binary`]), and outputs can also write strings as their UTF-8;
+//! * decimals are numbers, which keep their scale in precise mode;
+//! * UUIDs are strings, durations are objects with `months`, `days` and `millis` fields and other
+//!   logical types are the number they're encoded as, such as milliseconds since the Unix epoch.
+//!
+//! Outputs are written with a schema in the same representation. A union is written as its first
+//! branch that the value can be written as, records fill in missing fields with their defaults and
+//! fields that the record doesn't have are an error.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use anyhow::{bail, Context, Result};
+use apache_avro::reader::datum::GenericDatumReader;
+use apache_avro::schema::{InnerDecimalSchema, Name, NamespaceRef, ResolvedSchema};
+use apache_avro::types::Value;
+use apache_avro::writer::datum::GenericDatumWriter;
+use apache_avro::{Days, Decimal, Duration, Millis, Months, Schema, Uuid};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use jaq_interpret::Val;
+use num_bigint::BigInt;
+use redpanda_transform_sdk_sr::{decode_schema_id, SchemaId};
+
+use crate::config::BinaryValues;
+use crate::registry::Registry;
+use crate::{binary, headers, json};
+
+/// A parsed Avro schema, along with the schemas that it references.
+pub struct AvroSchema {
+    id: SchemaId,
+    schema: Schema,
+    /// The schemas `schema` references, each after those it references in turn.
+    references: Vec<Schema>,
+    /// The named types of `schema` and its references, to look up the types they reference.
+    names: HashMap<Name, Schema>,
+}
+
+/// Read a record value, looking up the schema it was written with in the registry.
+pub fn decode(
+    registry: &Registry,
+    value: &[u8],
+    precise: bool,
+    binary: BinaryValues,
+) -> Result<Val> {
+    let (id, datum) = decode_schema_id(value)?;
+    registry.avro(id)?.read(datum, precise, binary)
+}
+
+impl AvroSchema {
+    /// Parse the definition of the schema registered with `id`, where `references` are the
+    /// definitions of the schemas it references.
+    pub fn parse(id: SchemaId, definition: &str, references: &[String]) -> Result<Self> {
+        let (schema, references) = Schema::parse_str_with_list(definition, references)?;
+        let mut schemata: Vec<&Schema> = references.iter().collect();
+        schemata.push(&schema);
+        let names = ResolvedSchema::new_with_schemata(schemata)?
+            .get_names()
+            .iter()
+            .map(|(name, schema)| (name.clone(), (*schema).clone()))
+            .collect();
+        Ok(Self {
+            id,
+            schema,
+            references,
+            names,
+        })
+    }
+
+    fn schemata(&self) -> Vec<&Schema> {
+        self.references.iter().chain([&self.schema]).collect()
+    }
+
+    /// Read a value written with this schema, without the wire format's header.
+    fn read(&self, mut datum: &[u8], precise: bool, binary: BinaryValues) -> Result<Val> {
+        let value = GenericDatumReader::builder(&self.schema)
+            .writer_schemata(self.schemata())?
+            .build()?
+            .read_value(&mut datum)?;
+        if !datum.is_empty() {
+            bail!("{} unexpected bytes after the Avro value", datum.len());
+        }
+        self.val_from_value(value, &self.schema, None, precise, binary)
+    }
+
+    /// Write `val` in the wire format with this schema, appending it to `out`.
+    pub fn write(&self, val: &Val, out: &mut Vec<u8>, binary: BinaryValues) -> Result<()> {
+        let value = self.value_from_val(val, &self.schema, None, binary)?;
+        // The same header as `encode_schema_id` writes, without copying the value.
+        out.push(0);
+        out.extend_from_slice(&self.id.0.to_be_bytes());
+        GenericDatumWriter::builder(&self.schema)
+            .schemata(self.schemata())?
+            .build()?
+            .write_value_ref(out, &value)?;
+        Ok(())
+    }
+
+    /// Follow `schema` to the type it names if it's a reference to a named type.
+    fn resolve<'a>(&'a self, schema: &'a Schema, ns: NamespaceRef) -> Result<&'a Schema> {
+        match schema {
+            Schema::Ref { name } => {
+                let name = name.fully_qualified_name(ns);
+                self.names
+                    .get(&name)
+                    .with_context(|| format!("schema references unknown type {name}"))
+            }
+            schema => Ok(schema),
+        }
+    }
+
+    fn val_from_value(
+        &self,
+        value: Value,
+        schema: &Schema,
+        ns: NamespaceRef,
+        precise: bool,
+        binary: BinaryValues,
+    ) -> Result<Val> {
+        let schema = self.resolve(schema, ns)?;
+        Ok(match (value, schema) {
+            (Value::Null, _) => Val::Null,
+            (Value::Boolean(b), _) => Val::Bool(b),
+            (Value::Int(i) | Value::Date(i) | Value::TimeMillis(i), _) => Val::Int(i as isize),
+            (
+                Value::Long(i)
+                | Value::TimeMicros(i)
+                | Value::TimestampMillis(i)
+                | Value::TimestampMicros(i)
+                | Value::TimestampNanos(i)
+                | Value::LocalTimestampMillis(i)
+                | Value::LocalTimestampMicros(i)
+                | Value::LocalTimestampNanos(i),
+                _,
+            ) => number(&i.to_string(), true, precise)?,
+            // Read floats as the shortest double that prints the same, so that 0.1 isn't read as
+            // 0.10000000149011612.
+            (Value::Float(f), _) => Val::Float(f.to_string().parse()?),
+            (Value::Double(f), _) => Val::Float(f),
+            (Value::Bytes(b) | Value::Fixed(_, b), _) => binary::bytes_to_val(&b, binary),
+            (Value::String(s) | Value::Enum(_, s), _) => Val::str(s),
+            (Value::Uuid(uuid), _) => Val::str(uuid.to_string()),
+            (Value::Union(i, value), Schema::Union(union)) => {
+                let variant = union
+                    .variants()
+                    .get(i as usize)
+                    .context("union branch out of range")?;
+                self.val_from_value(*value, variant, ns, precise, binary)?
+            }
+            (Value::Array(items), Schema::Array(array)) => Val::arr(
+                items
+                    .into_iter()
+                    .map(|item| self.val_from_value(item, &array.items, ns, precise, binary))
+                    .collect::<Result<_>>()?,
+            ),
+            // Maps aren't ordered, so sort their keys to read them the same every time.
+            (Value::Map(entries), Schema::Map(map)) => {
+                let mut entries: Vec<_> = entries.into_iter().collect();
+                entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+                Val::obj(
+                    entries
+                        .into_iter()
+                        .map(|(k, v)| {
+                            Ok((
+                                Rc::new(k),
+                                self.val_from_value(v, &map.types, ns, precise, binary)?,
+                            ))
+                        })
+                        .collect::<Result<_>>()?,
+                )
+            }
+            (Value::Record(fields), Schema::Record(record)) => {
+                let ns = record.name.namespace();
+                Val::obj(
+                    fields
+                        .into_iter()
+                        .zip(&record.fields)
+                        .map(|((name, v), field)| {
+                            Ok((
+                                Rc::new(name),
+                                self.val_from_value(v, &field.schema, ns, precise, binary)?,
+                            ))
+                        })
+                        .collect::<Result<_>>()?,
+                )
+            }
+            (Value::Decimal(decimal), Schema::Decimal(schema)) => {
+                let text = decimal_to_string(&BigInt::from(decimal), schema.scale);
+                number(&text, schema.scale == 0, precise)?
+            }
+            (Value::BigDecimal(decimal), _) => {
+                let text = decimal.to_string();
+                number(&text, !text.contains(['.', 'e', 'E']), precise)?
+            }
+            (Value::Duration(duration), _) => Val::obj(
+                [
+                    ("months", u32::from(duration.months())),
+                    ("days", u32::from(duration.days())),
+                    ("millis", u32::from(duration.millis())),
+                ]
+                .into_iter()
+                .map(|(k, v)| (Rc::new(k.to_owned()), Val::Int(v as isize)))
+                .collect(),
+            ),
+            (value, _) => bail!("cannot read {value:?} as {}", describe(schema)),
+        })
+    }
+
+    fn value_from_val(
+        &self,
+        val: &Val,
+        schema: &Schema,
+        ns: NamespaceRef,
+        binary: BinaryValues,
+    ) -> Result<Value> {
+        let schema = self.resolve(schema, ns)?;
+        let mismatch = || format!("expected {}, got {val}", describe(schema));
+        let long = || integer(val).with_context(mismatch);
+        let int = || {
+            integer(val)
+                .and_then(|i| i32::try_from(i).ok())
+                .with_context(mismatch)
+        };
+        let bytes = || match binary {
+            BinaryValues::Object => headers::bytes_from_val(val).ok().flatten(),
+            BinaryValues::String => match val {
+                Val::Str(s) => STANDARD.decode(&***s).ok(),
+                _ => None,
+            },
+        };
+        Ok(match schema {
+            Schema::Null if *val == Val::Null => Value::Null,
+            Schema::Boolean => match val {
+                Val::Bool(b) => Value::Boolean(*b),
+                _ => bail!(mismatch()),
+            },
+            Schema::Int => Value::Int(int()?),
+            Schema::Long => Value::Long(long()?),
+            Schema::Float => Value::Float(float(val).with_context(mismatch)? as f32),
+            Schema::Double => Value::Double(float(val).with_context(mismatch)?),
+            Schema::Bytes => Value::Bytes(bytes().with_context(mismatch)?),
+            Schema::String => match val {
+                Val::Str(s) => Value::String(s.to_string()),
+                _ => bail!(mismatch()),
+            },
+            Schema::Array(array) => match val {
+                Val::Arr(items) => Value::Array(
+                    items
+                        .iter()
+                        .map(|item| self.value_from_val(item, &array.items, ns, binary))
+                        .collect::<Result<_>>()?,
+                ),
+                _ => bail!(mismatch()),
+            },
+            Schema::Map(map) => match val {
+                Val::Obj(entries) => Value::Map(
+                    entries
+                        .iter()
+                        .map(|(k, v)| {
+                            let v = self
+                                .value_from_val(v, &map.types, ns, binary)
+                                .with_context(|| format!("map entry {k:?}"))?;
+                            Ok((k.to_string(), v))
+                        })
+                        .collect::<Result<_>>()?,
+                ),
+                _ => bail!(mismatch()),
+            },
+            Schema::Union(union) => {
+                let branch = union
+                    .variants()
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, variant)| {
+                        Some((i, self.value_from_val(val, variant, ns, binary).ok()?))
+                    });
+                match branch {
+                    Some((i, value)) => Value::Union(i as u32, Box::new(value)),
+                    None => bail!(mismatch()),
+                }
+            }
+            Schema::Record(record) => {
+                let Val::Obj(obj) = val else {
+                    bail!(mismatch());
+                };
+                if let Some(name) = obj.keys().find(|k| !record.lookup.contains_key(k.as_str())) {
+                    bail!("unexpected field {name:?} for record {}", record.name);
+                }
+                let ns = record.name.namespace();
+                let mut fields = Vec::with_capacity(record.fields.len());
+                for field in &record.fields {
+                    let value = match (obj.get(&field.name), &field.default) {
+                        (Some(v), _) => v.clone(),
+                        (None, Some(default)) => Val::from(default.clone()),
+                        (None, None) => {
+                            bail!("missing field {:?} of record {}", field.name, record.name)
+                        }
+                    };
+                    let value = self
+                        .value_from_val(&value, &field.schema, ns, binary)
+                        .with_context(|| format!("field {:?}", field.name))?;
+                    fields.push((field.name.clone(), value));
+                }
+                Value::Record(fields)
+            }
+            Schema::Enum(schema) => match val {
+                Val::Str(s) => match schema.symbols.iter().position(|sym| **sym == **s) {
+                    Some(i) => Value::Enum(i as u32, s.to_string()),
+                    None => bail!("{val} is not a symbol of enum {}", schema.name),
+                },
+                _ => bail!(mismatch()),
+            },
+            Schema::Fixed(schema) => match bytes() {
+                Some(b) if b.len() == schema.size => Value::Fixed(schema.size, b),
+                _ => bail!(mismatch()),
+            },
+            Schema::Decimal(schema) => {
+                let unscaled = number_text(val)
+                    .context("not a number")
+                    .and_then(|text| decimal_from_str(&text, schema.precision, schema.scale))
+                    .with_context(mismatch)?;
+                if let InnerDecimalSchema::Fixed(fixed) = &schema.inner {
+                    if unscaled.to_signed_bytes_be().len() > fixed.size {
+                        bail!("{val} does not fit in {} bytes", fixed.size);
+                    }
+                }
+                Value::Decimal(Decimal::from(unscaled.to_signed_bytes_be()))
+            }
+            Schema::BigDecimal => bail!("writing big-decimal values is not supported"),
+            Schema::Uuid(_) => match val {
+                Val::Str(s) => Value::Uuid(Uuid::parse_str(s).with_context(mismatch)?),
+                _ => bail!(mismatch()),
+            },
+            Schema::Date => Value::Date(int()?),
+            Schema::TimeMillis => Value::TimeMillis(int()?),
+            Schema::TimeMicros => Value::TimeMicros(long()?),
+            Schema::TimestampMillis => Value::TimestampMillis(long()?),
+            Schema::TimestampMicros => Value::TimestampMicros(long()?),
+            Schema::TimestampNanos => Value::TimestampNanos(long()?),
+            Schema::LocalTimestampMillis => Value::LocalTimestampMillis(long()?),
+            Schema::LocalTimestampMicros => Value::LocalTimestampMicros(long()?),
+            Schema::LocalTimestampNanos => Value::LocalTimestampNanos(long()?),
+            Schema::Duration(_) => {
+                let Val::Obj(obj) = val else {
+                    bail!(mismatch());
+                };
+                let part = |name: &str| {
+                    obj.get(&name.to_owned())
+                        .and_then(integer)
+                        .and_then(|i| u32::try_from(i).ok())
+                        .with_context(|| format!("duration field {name:?} must be an integer"))
+                };
+                Value::Duration(Duration::new(
+                    Months::new(part("months")?),
+                    Days::new(part("days")?),
+                    Millis::new(part("millis")?),
+                ))
+            }
+            Schema::Null | Schema::Ref { .. } => bail!(mismatch()),
+        })
+    }
+}
+
+fn number(text: &str, integer: bool, precise: bool) -> Result<Val> {
+    json::number(text, integer, precise).with_context(|| format!("number {text} is out of range"))
+}
+
+/// The name of a type for errors, which is the name of named types.
+fn describe(schema: &Schema) -> String {
+    match schema {
+        Schema::Record(s) => format!("record {}", s.name),
+        Schema::Enum(s) => format!("enum {}", s.name),
+        Schema::Fixed(s) => format!("fixed {} of {} bytes", s.name, s.size),
+        Schema::Bytes => "bytes".to_owned(),
+        Schema::Union(_) => "a value of one of the union's branches".to_owned(),
+        Schema::Duration(_) => "duration object".to_owned(),
+        Schema::Ref { name } => name.to_string(),
+        schema => {
+            // The JSON of other types is their name, or an object with their logical type.
+            let json = serde_json::to_value(schema).unwrap_or_default();
+            match json.get("logicalType").unwrap_or(&json) {
+                serde_json::Value::String(name) => name.clone(),
+                json => json.to_string(),
+            }
+        }
+    }
+}
+
+/// The value of an integer, or a number without a fraction.
+fn integer(val: &Val) -> Option<i64> {
+    match val {
+        Val::Int(i) => Some(*i as i64),
+        Val::Float(f) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => Some(*f as i64),
+        Val::Num(n) => n
+            .parse()
+            .ok()
+            .or_else(|| integer(&Val::Float(n.parse().ok()?))),
+        _ => None,
+    }
+}
+
+fn float(val: &Val) -> Option<f64> {
+    match val {
+        Val::Int(i) => Some(*i as f64),
+        Val::Float(f) => Some(*f),
+        Val::Num(n) => n.parse().ok(),
+        _ => None,
+    }
+}
+
+/// The text of a number, which is exact for numbers kept in precise mode.
+fn number_text(val: &Val) -> Option<String> {
+    match val {
+        Val::Int(i) => Some(i.to_string()),
+        Val::Float(f) if f.is_finite() => Some(f.to_string()),
+        Val::Num(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// The unscaled value of a decimal number with at most `precision` digits, `scale` of which are
+/// after the point.
+///
+/// The digits are counted before the unscaled value is written out, so that a number with a large
+/// exponent such as `1e999999999999` fails instead of allocating its zeros.
+fn decimal_from_str(text: &str, precision: usize, scale: usize) -> Result<BigInt> {
+    let (mantissa, exp) = match text.split_once(['e', 'E']) {
+        Some((mantissa, exp)) => (mantissa, exp.parse::<i64>().context("invalid exponent")?),
+        None => (text, 0),
+    };
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa.trim_start_matches('+')),
+    };
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let frac = frac.trim_end_matches('0');
+    let digits = format!("{int}{frac}");
+    let digits = digits.trim_start_matches('0');
+    if digits.is_empty() {
+        return Ok(BigInt::default());
+    }
+    // The number of zeros to append for `scale` digits after the point once the exponent is
+    // applied, which is negative if there are more.
+    let zeros = (scale as i64)
+        .checked_add(exp)
+        .and_then(|n| n.checked_sub(frac.len() as i64))
+        .context("exponent is out of range")?;
+    let Ok(zeros) = usize::try_from(zeros) else {
+        bail!("has more than {scale} digits after the point");
+    };
+    if digits.len().saturating_add(zeros) > precision {
+        bail!("has more than {precision} digits");
+    }
+    let unscaled = format!("{sign}{digits}{}", "0".repeat(zeros));
+    BigInt::parse_bytes(unscaled.as_bytes(), 10).context("not a number")
+}
+
+fn decimal_to_string(unscaled: &BigInt, scale: usize) -> String {
+    let digits = unscaled.magnitude().to_string();
+    let sign = if unscaled.sign() == num_bigint::Sign::Minus {
+        "-"
+    } else {
+        ""
+    };
+    if scale == 0 {
+        return format!("{sign}{digits}");
+    }
+    let digits = format!("{digits:0>width$}", width = scale + 1);
+    let (int, frac) = digits.split_at(digits.len() - scale);
+    format!("{sign}{int}.{frac}")
+}
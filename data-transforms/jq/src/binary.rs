@@ -0,0 +1,325 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! MessagePack and CBOR, which are read into JSON and written from it.
+//!
+//! Both have the values of JSON and a few more, which are mapped to JSON as follows:
+//!
+//! * Binary strings are `{"base64": "..."}` objects, the same as header values that aren't UTF-8,
+//!   and outputs of that shape are written as binary strings. With [`BinaryValues::String`] they
+//!   are base64 strings instead, and outputs only write text strings.
+//! * Text strings that aren't valid UTF-8 have invalid sequences replaced with `U+FFFD`.
+//! * Map keys that are strings are field names as is, binary keys are base64 and other keys are
+//!   their JSON text, so the key `1` is the field `"1"`.
+//! * MessagePack extension types are `{"ext": type, "base64": "..."}` objects, and outputs of that
+//!   shape are written as extension types.
+//! * CBOR tags are left out, leaving their content, except that bignums are integers. `undefined`
+//!   and other simple values are `null`.
+//!
+//! Numbers are read like JSON numbers, so integers that don't fit in a double only keep all of
+//! their digits in precise mode, and objects are sorted unless in precise mode. Outputs that are
+//! integers are written as integers, and other numbers as doubles, except that CBOR writes integers
+//! that don't fit in 64 bits as bignums and MessagePack writes them as doubles.
+
+use std::rc::Rc;
+
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use jaq_interpret::Val;
+use num_bigint::{BigInt, Sign};
+
+use crate::config::BinaryValues;
+use crate::json;
+
+// CBOR tags of positive and negative bignums.
+const BIGNUM: u64 = 2;
+const NEGATIVE_BIGNUM: u64 = 3;
+
+const BASE64_FIELD: &str = "base64";
+const EXT_FIELD: &str = "ext";
+
+/// Read a single MessagePack value.
+pub fn read_msgpack(value: &[u8], precise: bool, binary: BinaryValues) -> Result<Val> {
+    let mut rest = value;
+    let value = rmpv::decode::read_value_with_max_depth(&mut rest, json::MAX_DEPTH)
+        .context("invalid MessagePack")?;
+    if !rest.is_empty() {
+        bail!(
+            "{} unexpected bytes after the MessagePack value",
+            rest.len()
+        );
+    }
+    from_msgpack(value, precise, binary)
+}
+
+/// Read a single CBOR value.
+pub fn read_cbor(value: &[u8], precise: bool, binary: BinaryValues) -> Result<Val> {
+    let mut rest = value;
+    let value: ciborium::Value =
+        ciborium::de::from_reader_with_recursion_limit(&mut rest, json::MAX_DEPTH)
+            .context("invalid CBOR")?;
+    if !rest.is_empty() {
+        bail!("{} unexpected bytes after the CBOR value", rest.len());
+    }
+    from_cbor(value, precise, binary)
+}
+
+/// Write a value as MessagePack, appending it to `out`.
+pub fn write_msgpack(
+    out: &mut Vec<u8>,
+    val: &Val,
+    precise: bool,
+    binary: BinaryValues,
+) -> Result<()> {
+    rmpv::encode::write_value(out, &to_msgpack(val, precise, binary)?)?;
+    Ok(())
+}
+
+/// Write a value as CBOR, appending it to `out`.
+pub fn write_cbor(out: &mut Vec<u8>, val: &Val, precise: bool, binary: BinaryValues) -> Result<()> {
+    ciborium::ser::into_writer(&to_cbor(val, precise, binary)?, out)?;
+    Ok(())
+}
+
+fn from_msgpack(value: rmpv::Value, precise: bool, binary: BinaryValues) -> Result<Val> {
+    use rmpv::Value;
+    Ok(match value {
+        Value::Nil => Val::Null,
+        Value::Boolean(b) => Val::Bool(b),
+        Value::Integer(i) => integer(&i.to_string(), precise)?,
+        // Doubles of singles have digits the single didn't, such as `0.10000000149011612`.
+        Value::F32(f) => Val::Float(f.to_string().parse()?),
+        Value::F64(f) => Val::Float(f),
+        Value::String(s) => Val::str(String::from_utf8_lossy(s.as_bytes()).into_owned()),
+        Value::Binary(b) => bytes_to_val(&b, binary),
+        Value::Array(a) => Val::arr(
+            a.into_iter()
+                .map(|v| from_msgpack(v, precise, binary))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Map(m) => object(
+            m.into_iter()
+                .map(|(k, v)| {
+                    let key = match k {
+                        Value::String(s) => String::from_utf8_lossy(s.as_bytes()).into_owned(),
+                        Value::Binary(b) => STANDARD.encode(b),
+                        k => key(&from_msgpack(k, precise, binary)?)?,
+                    };
+                    Ok((key, from_msgpack(v, precise, binary)?))
+                })
+                .collect::<Result<_>>()?,
+            precise,
+        ),
+        Value::Ext(ty, data) => Val::obj(
+            [
+                (Rc::new(EXT_FIELD.to_owned()), Val::Int(ty.into())),
+                (
+                    Rc::new(BASE64_FIELD.to_owned()),
+                    Val::str(STANDARD.encode(data)),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        ),
+    })
+}
+
+fn from_cbor(value: ciborium::Value, precise: bool, binary: BinaryValues) -> Result<Val> {
+    use ciborium::Value;
+    Ok(match value {
+        Value::Null => Val::Null,
+        Value::Bool(b) => Val::Bool(b),
+        Value::Integer(i) => integer(&i128::from(i).to_string(), precise)?,
+        Value::Float(f) => Val::Float(f),
+        Value::Text(s) => Val::str(s),
+        Value::Bytes(b) => bytes_to_val(&b, binary),
+        Value::Array(a) => Val::arr(
+            a.into_iter()
+                .map(|v| from_cbor(v, precise, binary))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Map(m) => object(
+            m.into_iter()
+                .map(|(k, v)| {
+                    let key = match k {
+                        Value::Text(s) => s,
+                        Value::Bytes(b) => STANDARD.encode(b),
+                        k => key(&from_cbor(k, precise, binary)?)?,
+                    };
+                    Ok((key, from_cbor(v, precise, binary)?))
+                })
+                .collect::<Result<_>>()?,
+            precise,
+        ),
+        Value::Tag(tag @ (BIGNUM | NEGATIVE_BIGNUM), content) => match *content {
+            Value::Bytes(b) => {
+                let n = BigInt::from_bytes_be(Sign::Plus, &b);
+                let n = if tag == NEGATIVE_BIGNUM { -1 - n } else { n };
+                integer(&n.to_string(), precise)?
+            }
+            content => from_cbor(content, precise, binary)?,
+        },
+        Value::Tag(_, content) => from_cbor(*content, precise, binary)?,
+        _ => Val::Null,
+    })
+}
+
+fn to_msgpack(val: &Val, precise: bool, binary: BinaryValues) -> Result<rmpv::Value> {
+    use rmpv::Value;
+    Ok(match val {
+        Val::Null => Value::Nil,
+        Val::Bool(b) => Value::Boolean(*b),
+        Val::Int(i) => Value::from(*i as i64),
+        Val::Float(f) => Value::F64(*f),
+        Val::Num(n) => match n.parse::<BigInt>() {
+            Ok(i) => match (i64::try_from(&i), u64::try_from(&i)) {
+                (Ok(i), _) => Value::from(i),
+                (_, Ok(u)) => Value::from(u),
+                _ => Value::F64(n.parse()?),
+            },
+            Err(_) => Value::F64(n.parse()?),
+        },
+        Val::Str(s) => Value::from(s.as_str()),
+        Val::Arr(a) => Value::Array(
+            a.iter()
+                .map(|v| to_msgpack(v, precise, binary))
+                .collect::<Result<_>>()?,
+        ),
+        Val::Obj(o) => match special(val, binary)? {
+            Some(Special::Bytes(b)) => Value::Binary(b),
+            Some(Special::Ext(ty, data)) => Value::Ext(ty, data),
+            None => Value::Map(
+                fields(o.iter(), precise)
+                    .into_iter()
+                    .map(|(k, v)| Ok((Value::from(k.as_str()), to_msgpack(v, precise, binary)?)))
+                    .collect::<Result<_>>()?,
+            ),
+        },
+    })
+}
+
+fn to_cbor(val: &Val, precise: bool, binary: BinaryValues) -> Result<ciborium::Value> {
+    use ciborium::Value;
+    Ok(match val {
+        Val::Null => Value::Null,
+        Val::Bool(b) => Value::Bool(*b),
+        Val::Int(i) => Value::Integer((*i as i64).into()),
+        Val::Float(f) => Value::Float(*f),
+        Val::Num(n) => match n.parse::<BigInt>() {
+            Ok(i) => match i128::try_from(&i).ok().and_then(|i| i.try_into().ok()) {
+                Some(i) => Value::Integer(i),
+                None => {
+                    let (tag, n) = match i.sign() {
+                        Sign::Minus => (NEGATIVE_BIGNUM, -1 - i),
+                        _ => (BIGNUM, i),
+                    };
+                    Value::Tag(tag, Box::new(Value::Bytes(n.to_bytes_be().1)))
+                }
+            },
+            Err(_) => Value::Float(n.parse()?),
+        },
+        Val::Str(s) => Value::Text(s.to_string()),
+        Val::Arr(a) => Value::Array(
+            a.iter()
+                .map(|v| to_cbor(v, precise, binary))
+                .collect::<Result<_>>()?,
+        ),
+        Val::Obj(o) => match special(val, binary)? {
+            Some(Special::Bytes(b)) => Value::Bytes(b),
+            _ => Value::Map(
+                fields(o.iter(), precise)
+                    .into_iter()
+                    .map(|(k, v)| Ok((Value::Text(k.to_string()), to_cbor(v, precise, binary)?)))
+                    .collect::<Result<_>>()?,
+            ),
+        },
+    })
+}
+
+/// Read an integer from its text like a JSON number.
+fn integer(num: &str, precise: bool) -> Result<Val> {
+    json::number(num, true, precise).with_context(|| format!("integer {num} is out of range"))
+}
+
+/// Binary strings are `{"base64": "..."}` objects, or base64 strings with
+/// [`BinaryValues::String`].
+pub fn bytes_to_val(bytes: &[u8], binary: BinaryValues) -> Val {
+    let base64 = Val::str(STANDARD.encode(bytes));
+    match binary {
+        BinaryValues::Object => Val::obj(
+            [(Rc::new(BASE64_FIELD.to_owned()), base64)]
+                .into_iter()
+                .collect(),
+        ),
+        BinaryValues::String => base64,
+    }
+}
+
+/// The field name of a map key that isn't a string, which is its JSON text.
+fn key(val: &Val) -> Result<String> {
+    let mut text = Vec::new();
+    json::write(&mut text, val, true)?;
+    Ok(String::from_utf8(text)?)
+}
+
+fn object(mut fields: Vec<(String, Val)>, precise: bool) -> Val {
+    // Sorting is stable, so the last of duplicate fields still wins, like in JSON.
+    if !precise {
+        fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+    }
+    Val::obj(fields.into_iter().map(|(k, v)| (Rc::new(k), v)).collect())
+}
+
+/// The fields of an object in the order they're written, which is sorted unless in precise mode.
+fn fields<'a>(
+    o: impl Iterator<Item = (&'a Rc<String>, &'a Val)>,
+    precise: bool,
+) -> Vec<(&'a Rc<String>, &'a Val)> {
+    let mut fields: Vec<_> = o.collect();
+    if !precise {
+        fields.sort_unstable_by_key(|(k, _)| *k);
+    }
+    fields
+}
+
+/// An output object that stands for a value JSON doesn't have.
+enum Special {
+    Bytes(Vec<u8>),
+    Ext(i8, Vec<u8>),
+}
+
+fn special(val: &Val, binary: BinaryValues) -> Result<Option<Special>> {
+    let Val::Obj(o) = val else {
+        return Ok(None);
+    };
+    let base64 = match o.get(&BASE64_FIELD.to_owned()) {
+        Some(Val::Str(s)) => s,
+        _ => return Ok(None),
+    };
+    let decode = || {
+        STANDARD
+            .decode(base64.as_bytes())
+            .with_context(|| format!("{base64:?} is not valid base64"))
+    };
+    match (o.len(), o.get(&EXT_FIELD.to_owned())) {
+        (1, None) if binary == BinaryValues::Object => Ok(Some(Special::Bytes(decode()?))),
+        (2, Some(Val::Int(ty))) => {
+            let ty = i8::try_from(*ty)
+                .with_context(|| format!("extension type {ty} is out of range"))?;
+            Ok(Some(Special::Ext(ty, decode()?)))
+        }
+        _ => Ok(None),
+    }
+}
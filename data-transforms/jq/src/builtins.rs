@@ -0,0 +1,164 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Functions available to filters in addition to the jq standard library, and the natives of jaq
+//! that the transform changes.
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use jaq_interpret::results::box_once;
+use jaq_interpret::{Error, FilterT, Native, RunPtr, UpdatePtr, Val};
+
+use crate::prelude::{self, STEP};
+use crate::{bytes, datetime, redact};
+
+const NATIVES: &[(&str, usize, RunPtr)] = &[("__restore_order", 1, |args, cv| {
+    Box::new(
+        args.get(0)
+            .run(cv.clone())
+            .map(move |orig| Ok(restore_order(cv.1.clone(), &orig?))),
+    )
+})];
+
+const UPDATE_NATIVES: &[(&str, usize, RunPtr, UpdatePtr)] = &[(
+    STEP,
+    0,
+    |_, cv| box_once(step().map(|()| cv.1)),
+    |_, cv, f| match step() {
+        Ok(()) => f(cv.1),
+        Err(e) => box_once(Err(e)),
+    },
+)];
+
+/// Our natives, which are those of [`prelude::NATIVES`], followed by those of jaq that they don't
+/// replace.
+pub fn natives() -> impl Iterator<Item = (String, usize, Native)> {
+    let run = |natives: &'static [(&str, usize, RunPtr)]| {
+        natives
+            .iter()
+            .map(|&(name, arity, run)| (name.to_owned(), arity, Native::new(run)))
+    };
+    let update = UPDATE_NATIVES.iter().map(|&(name, arity, run, update)| {
+        (name.to_owned(), arity, Native::with_update(run, update))
+    });
+    let ours: Vec<_> = run(NATIVES)
+        .chain(update)
+        .chain(run(bytes::NATIVES))
+        .chain(run(datetime::NATIVES))
+        .chain(run(redact::NATIVES))
+        .collect();
+    debug_assert!(
+        ours.iter()
+            .map(|(name, arity, _)| (name.as_str(), *arity))
+            .eq(prelude::NATIVES.iter().copied()),
+        "prelude::NATIVES must list the natives in order"
+    );
+    prelude::with_core(ours.into_iter())
+}
+
+thread_local! {
+    /// How many more steps the filter can take on the record it's running on.
+    static STEPS: Cell<u64> = const { Cell::new(u64::MAX) };
+}
+
+/// Allow the filter to take `max` steps on the next record it runs on, or any number for `None`.
+pub fn reset_steps(max: Option<u64>) {
+    STEPS.set(max.unwrap_or(u64::MAX));
+}
+
+/// Whether the filter used up its steps, in which case its outputs must be discarded as the error
+/// it failed with could have been caught with `try`.
+pub fn steps_exhausted() -> bool {
+    STEPS.get() == 0
+}
+
+fn step() -> Result<(), Error> {
+    match STEPS.get() {
+        0 => Err(Error::Val(Val::str("step limit exceeded".to_owned()))),
+        n => {
+            STEPS.set(n - 1);
+            Ok(())
+        }
+    }
+}
+
+/// Order the fields of `val`'s objects like those of `orig`, with fields that `orig` doesn't have
+/// last. Values that weren't changed are shared with `orig`, so only changed ones are visited.
+fn restore_order(val: Val, orig: &Val) -> Val {
+    match (val, orig) {
+        (Val::Obj(o), Val::Obj(orig)) if !Rc::ptr_eq(&o, orig) => {
+            let mut o = Rc::unwrap_or_clone(o);
+            for (k, v) in o.iter_mut() {
+                if let Some(orig) = orig.get(k) {
+                    *v = restore_order(v.clone(), orig);
+                }
+            }
+            o.sort_by_cached_key(|k, _| orig.get_index_of(k).unwrap_or(usize::MAX));
+            Val::obj(o)
+        }
+        // Elements of arrays keep their order, but some may have been deleted and the objects in
+        // the others changed. Unchanged elements are found in `orig` by identity, and each changed
+        // one is ordered like the next array or object in `orig` after the last one found. Only as
+        // many elements as were deleted can be skipped, which keeps this linear for small deletes.
+        (Val::Arr(a), Val::Arr(orig)) if !Rc::ptr_eq(&a, orig) => {
+            let a = Rc::unwrap_or_clone(a);
+            let mut left = a.len();
+            let mut rest = &orig[..];
+            let a = a.into_iter().map(|v| {
+                if rest.is_empty() {
+                    return v;
+                }
+                let window = &rest[..=rest.len().saturating_sub(left)];
+                left -= 1;
+                let found = match window.iter().position(|o| same(&v, o)) {
+                    Some(i) => Some((i, false)),
+                    None => window
+                        .iter()
+                        .position(|o| same_type(&v, o))
+                        .map(|i| (i, true)),
+                };
+                let Some((i, changed)) = found else {
+                    return v;
+                };
+                let next = &rest[i];
+                rest = &rest[i + 1..];
+                if changed {
+                    restore_order(v, next)
+                } else {
+                    v
+                }
+            });
+            Val::arr(a.collect())
+        }
+        (val, _) => val,
+    }
+}
+
+/// Whether both values are arrays or both are objects.
+fn same_type(a: &Val, b: &Val) -> bool {
+    matches!(
+        (a, b),
+        (Val::Arr(_), Val::Arr(_)) | (Val::Obj(_), Val::Obj(_))
+    )
+}
+
+/// Whether two arrays or objects are the same value in memory.
+fn same(a: &Val, b: &Val) -> bool {
+    match (a, b) {
+        (Val::Arr(a), Val::Arr(b)) => Rc::ptr_eq(a, b),
+        (Val::Obj(a), Val::Obj(b)) => Rc::ptr_eq(a, b),
+        _ => false,
+    }
+}
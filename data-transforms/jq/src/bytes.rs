@@ -0,0 +1,246 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Functions of filters on bytes: hashes, encodings and identifiers.
+//!
+//! Bytes are strings, as UTF-8, or binary data as `{"base64": "..."}` objects, the same as header
+//! values and binary strings of the `msgpack` and `cbor` formats. Hashes are written as lowercase
+//! hex, except for the Kafka partition hashes which are numbers. The formats `@hex` and `@base64d`
+//! work on text like the other formats of jq, so binary data has functions of its own.
+
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::rc::Rc;
+
+use base64::alphabet;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig, STANDARD};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use jaq_interpret::results::box_once;
+use jaq_interpret::{Error, FilterT, RunPtr, Val, ValR};
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+const BASE64_FIELD: &str = "base64";
+
+/// Decodes base64 with or without padding, like jq.
+const BASE64_DECODE: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// The natives. `@base64d` replaces jaq's so that it accepts input without padding, like jq.
+pub const NATIVES: &[(&str, usize, RunPtr)] = &[
+    ("sha256", 0, |_, cv| box_once(digest::<Sha256>(&cv.1))),
+    ("sha1", 0, |_, cv| box_once(digest::<Sha1>(&cv.1))),
+    ("md5", 0, |_, cv| box_once(digest::<Md5>(&cv.1))),
+    ("hmac_sha256", 1, |args, cv| {
+        Box::new(
+            args.get(0)
+                .run(cv.clone())
+                .map(move |key| hmac_sha256(&cv.1, &key?)),
+        )
+    }),
+    ("xxhash64", 0, |_, cv| {
+        box_once(bytes(&cv.1).map(|b| {
+            let hash = xxhash_rust::xxh64::xxh64(&b, 0);
+            Val::str(format!("{hash:016x}"))
+        }))
+    }),
+    ("murmur2", 0, |_, cv| {
+        box_once(bytes(&cv.1).map(|b| Val::Int(murmur2(&b) as isize)))
+    }),
+    ("murmur2_partition", 1, |args, cv| {
+        Box::new(
+            args.get(0)
+                .run(cv.clone())
+                .map(move |n| partition(&cv.1, &n?)),
+        )
+    }),
+    ("uuid_v5", 1, |args, cv| {
+        Box::new(
+            args.get(0)
+                .run(cv.clone())
+                .map(move |ns| uuid_v5(&cv.1, &ns?)),
+        )
+    }),
+    ("to_hex", 0, |_, cv| {
+        box_once(bytes(&cv.1).map(|b| Val::str(hex(&b))))
+    }),
+    ("to_base64", 0, |_, cv| {
+        box_once(bytes(&cv.1).map(|b| Val::str(STANDARD.encode(b))))
+    }),
+    ("from_base64", 0, |_, cv| {
+        box_once(decode_base64(&cv.1).map(from_bytes))
+    }),
+    ("@hex", 0, |_, cv| {
+        box_once(Ok(Val::str(hex(cv.1.to_string_or_clone().as_bytes()))))
+    }),
+    ("@base64d", 0, |_, cv| {
+        box_once(decode_base64(&cv.1).map(|b| Val::str(String::from_utf8_lossy(&b).into_owned())))
+    }),
+];
+
+/// The bytes of a string or binary data.
+fn bytes(val: &Val) -> Result<Cow<'_, [u8]>, Error> {
+    match val {
+        Val::Str(s) => Ok(Cow::Borrowed(s.as_bytes())),
+        Val::Obj(o) if o.len() == 1 => match o.get(&BASE64_FIELD.to_owned()) {
+            Some(Val::Str(b64)) => STANDARD
+                .decode(&***b64)
+                .map(Cow::Owned)
+                .map_err(|e| Error::str(format_args!("invalid base64 in {val}: {e}"))),
+            _ => Err(not_bytes(val)),
+        },
+        _ => Err(not_bytes(val)),
+    }
+}
+
+fn not_bytes(val: &Val) -> Error {
+    Error::str(format_args!(
+        "expected a string or {{\"base64\": ...}}, got {val}"
+    ))
+}
+
+/// Decode a string or the JSON text of another value as base64.
+fn decode_base64(val: &Val) -> Result<Vec<u8>, Error> {
+    BASE64_DECODE
+        .decode(val.to_string_or_clone())
+        .map_err(|e| Error::str(format_args!("cannot decode {val} as base64: {e}")))
+}
+
+/// Bytes that are valid UTF-8 are strings, other bytes are binary data.
+fn from_bytes(bytes: Vec<u8>) -> Val {
+    match String::from_utf8(bytes) {
+        Ok(s) => Val::str(s),
+        Err(e) => Val::obj(
+            [(
+                Rc::new(BASE64_FIELD.to_owned()),
+                Val::str(STANDARD.encode(e.as_bytes())),
+            )]
+            .into_iter()
+            .collect(),
+        ),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(hex, "{b:02x}");
+    }
+    hex
+}
+
+fn digest<D: Digest>(val: &Val) -> ValR {
+    Ok(Val::str(hex(&D::digest(bytes(val)?))))
+}
+
+fn hmac_sha256(val: &Val, key: &Val) -> ValR {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&bytes(key)?).map_err(Error::str)?;
+    mac.update(&bytes(val)?);
+    Ok(Val::str(hex(&mac.finalize().into_bytes())))
+}
+
+/// The partition of `n` partitions that Kafka's default partitioner writes a record with the key
+/// `val` to.
+fn partition(val: &Val, n: &Val) -> ValR {
+    let n = match n.as_int()? {
+        n if n > 0 => n as u32,
+        _ => {
+            return Err(Error::str(format_args!(
+                "cannot partition into {n} partitions"
+            )))
+        }
+    };
+    let hash = murmur2(&bytes(val)?) as u32 & 0x7fff_ffff;
+    Ok(Val::Int((hash % n) as isize))
+}
+
+/// The 32 bit MurmurHash2 of Kafka's default partitioner, `Utils.murmur2`.
+fn murmur2(data: &[u8]) -> i32 {
+    const SEED: u32 = 0x9747_b28c;
+    const M: u32 = 0x5bd1_e995;
+    const R: u32 = 24;
+    let mut h = SEED ^ data.len() as u32;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M);
+        h ^= k;
+    }
+    let rest = chunks.remainder();
+    if !rest.is_empty() {
+        for (i, b) in rest.iter().enumerate().rev() {
+            h ^= (*b as u32) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> 13;
+    h = h.wrapping_mul(M);
+    h ^= h >> 15;
+    h as i32
+}
+
+/// The name-based UUID (version 5) of `val` in the namespace `ns`, which is a UUID or one of the
+/// namespaces of RFC 9562: `dns`, `url`, `oid` or `x500`.
+fn uuid_v5(val: &Val, ns: &Val) -> ValR {
+    let ns = ns.as_str()?;
+    let ns = match ns.as_str() {
+        "dns" => "6ba7b810-9dad-11d1-80b4-00c04fd430c8",
+        "url" => "6ba7b811-9dad-11d1-80b4-00c04fd430c8",
+        "oid" => "6ba7b812-9dad-11d1-80b4-00c04fd430c8",
+        "x500" => "6ba7b814-9dad-11d1-80b4-00c04fd430c8",
+        ns => ns,
+    };
+    let ns = parse_uuid(ns).ok_or_else(|| Error::str(format_args!("invalid UUID {ns:?}")))?;
+    let mut hash = Sha1::new();
+    hash.update(ns);
+    hash.update(bytes(val)?);
+    let mut uuid = [0; 16];
+    uuid.copy_from_slice(&hash.finalize()[..16]);
+    uuid[6] = (uuid[6] & 0x0f) | 0x50;
+    uuid[8] = (uuid[8] & 0x3f) | 0x80;
+    let hex = hex(&uuid);
+    Ok(Val::str(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )))
+}
+
+/// Parse a UUID of the form `6ba7b810-9dad-11d1-80b4-00c04fd430c8`, in either case.
+fn parse_uuid(s: &str) -> Option<[u8; 16]> {
+    if !s.bytes().all(|b| b == b'-' || b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let groups: Vec<&str> = s.split('-').collect();
+    if groups.iter().map(|g| g.len()).collect::<Vec<_>>() != [8, 4, 4, 4, 12] {
+        return None;
+    }
+    let digits = groups.concat();
+    let mut uuid = [0; 16];
+    for (i, b) in uuid.iter_mut().enumerate() {
+        *b = u8::from_str_radix(digits.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(uuid)
+}
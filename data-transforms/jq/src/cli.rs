@@ -0,0 +1,273 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A native runner for trying out filters without deploying the transform to a cluster.
+//!
+//! Input records are read from JSON Lines or fixture directories and run through the same code as
+//! the deployed transform. Each record it would write is printed to stdout as a line of JSON.
+
+use std::fs;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{bail, Context, Result};
+use jaq_interpret::Val;
+use jq::config::Config;
+use jq::record::{self, InputRecord};
+use jq::registry::LocalRegistry;
+use jq::{headers, Transform};
+use redpanda_transform_sdk_sr::{Schema, SchemaFormat, SchemaRegistryClient};
+
+const USAGE: &str = "\
+usage: jq [--var=NAME=VALUE]... [--schema=SUBJECT=PATH]... [--records] [PATH]...
+
+Runs the transform on records read from each PATH and prints the records it writes.
+
+A PATH that is a file holds JSON Lines, where each line is a record value unless --records is
+given. A PATH that is a directory holds a subdirectory for each record, with optional key, value,
+headers.json and timestamp files. Without a PATH, or when PATH is -, JSON Lines are read from
+stdin.
+
+options:
+  --var=NAME=VALUE  Set an environment variable of the transform, like rpk transform deploy.
+  --schema=SUBJECT=PATH
+                    Register the schema in the file at PATH under SUBJECT, for the avro and
+                    protobuf formats and INPUT_SCHEMA_SUBJECT and OUTPUT_SCHEMA_SUBJECT. Files
+                    ending in .proto are Protobuf schemas, those ending in .schema.json are JSON
+                    Schemas and others are Avro. Schemas are given IDs from 1 in the order they
+                    are registered.
+  --records         Each line of JSON Lines input is a record of the form
+                    {\"key\": ..., \"value\": ..., \"headers\": {...}, \"timestamp\": ...}.
+";
+
+struct Args {
+    vars: Vec<(String, String)>,
+    registry: Option<LocalRegistry>,
+    records: bool,
+    paths: Vec<PathBuf>,
+}
+
+pub fn run() -> Result<()> {
+    let args = parse_args(std::env::args().skip(1))?;
+    let config = Config::from_vars(args.vars)?;
+    let transform = match args.registry {
+        Some(registry) => Transform::with_registry(
+            config,
+            SchemaRegistryClient::new_wrapping(Box::new(registry)),
+        )?,
+        None => Transform::new(config)?,
+    };
+    let mut out = BufWriter::new(io::stdout().lock());
+    let paths = if args.paths.is_empty() {
+        vec![PathBuf::from("-")]
+    } else {
+        args.paths
+    };
+    for path in &paths {
+        if path.is_dir() {
+            for (n, input) in read_fixtures(path)?.iter().enumerate() {
+                process(&transform, &mut out, input, n, path)?;
+            }
+        } else {
+            read_lines(path, args.records, |n, input| {
+                process(&transform, &mut out, &input, n, path)?;
+                // Records from stdin may be arriving as they're written, so print their outputs
+                // right away rather than when the buffer fills up.
+                if path == Path::new("-") {
+                    out.flush()?;
+                }
+                Ok(())
+            })?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Run the transform on the `n`th record of `path` and print the records it writes.
+fn process(
+    transform: &Transform,
+    out: &mut impl Write,
+    input: &InputRecord,
+    n: usize,
+    path: &Path,
+) -> Result<()> {
+    let outputs = transform
+        .process(&input.as_written())
+        .with_context(|| format!("record {} of {} failed", n + 1, display(path)))?;
+    for output in &outputs {
+        writeln!(out, "{}", record::output_to_json(output))?;
+    }
+    Ok(())
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args> {
+    let mut parsed = Args {
+        vars: Vec::new(),
+        registry: None,
+        records: false,
+        paths: Vec::new(),
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let var = match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{USAGE}");
+                std::process::exit(0);
+            }
+            "--records" => {
+                parsed.records = true;
+                continue;
+            }
+            "--var" => args
+                .next()
+                .context("--var requires a NAME=VALUE argument")?,
+            "--schema" => {
+                let schema = args
+                    .next()
+                    .context("--schema requires a SUBJECT=PATH argument")?;
+                register(&mut parsed, &schema)?;
+                continue;
+            }
+            _ if arg.starts_with("--schema=") => {
+                register(&mut parsed, &arg["--schema=".len()..])?;
+                continue;
+            }
+            _ if arg.starts_with("--var=") => arg["--var=".len()..].to_owned(),
+            "-" => {
+                parsed.paths.push(PathBuf::from(arg));
+                continue;
+            }
+            _ if arg.starts_with('-') => bail!("unknown option {arg:?}\n\n{USAGE}"),
+            _ => {
+                parsed.paths.push(PathBuf::from(arg));
+                continue;
+            }
+        };
+        let (name, value) = var
+            .split_once('=')
+            .with_context(|| format!("--var must be of the form NAME=VALUE, got {var:?}"))?;
+        parsed.vars.push((name.to_owned(), value.to_owned()));
+    }
+    Ok(parsed)
+}
+
+/// Register the schema of a `--schema=SUBJECT=PATH` argument.
+fn register(args: &mut Args, schema: &str) -> Result<()> {
+    let (subject, path) = schema
+        .split_once('=')
+        .with_context(|| format!("--schema must be of the form SUBJECT=PATH, got {schema:?}"))?;
+    let definition =
+        fs::read_to_string(path).with_context(|| format!("cannot read schema {path}"))?;
+    let schema = if path.ends_with(".proto") {
+        Schema::new_protobuf(definition, Vec::new())
+    } else if path.ends_with(".schema.json") {
+        Schema::new(definition, SchemaFormat::Json, Vec::new())
+    } else {
+        Schema::new_avro(definition, Vec::new())
+    };
+    args.registry
+        .get_or_insert_with(LocalRegistry::new)
+        .register(subject, schema);
+    Ok(())
+}
+
+/// Read JSON Lines from a file, or stdin if `path` is `-`, calling `f` with the index and record of
+/// each line as soon as it's read.
+fn read_lines(
+    path: &Path,
+    records: bool,
+    mut f: impl FnMut(usize, InputRecord) -> Result<()>,
+) -> Result<()> {
+    let reader: Box<dyn BufRead> = if path == Path::new("-") {
+        Box::new(io::stdin().lock())
+    } else {
+        let file =
+            fs::File::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+        Box::new(io::BufReader::new(file))
+    };
+    let mut count = 0;
+    for (n, line) in reader.lines().enumerate() {
+        let line = line.with_context(|| format!("cannot read {}", display(path)))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let input = if records {
+            serde_json::from_str(&line)
+                .map_err(anyhow::Error::from)
+                .and_then(InputRecord::from_json)
+                .with_context(|| format!("invalid record on line {} of {}", n + 1, display(path)))?
+        } else {
+            InputRecord::from_value(line.into_bytes())
+        };
+        f(count, input)?;
+        count += 1;
+    }
+    Ok(())
+}
+
+/// Read a directory with a subdirectory for each record, in order of name.
+fn read_fixtures(dir: &Path) -> Result<Vec<InputRecord>> {
+    let mut records: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("cannot read {}", dir.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<io::Result<_>>()?;
+    records.retain(|p| p.is_dir());
+    records.sort();
+    records
+        .iter()
+        .map(|record| {
+            read_fixture(record).with_context(|| format!("invalid record {}", record.display()))
+        })
+        .collect()
+}
+
+fn read_fixture(dir: &Path) -> Result<InputRecord> {
+    let read = |name: &str| -> Result<Option<Vec<u8>>> {
+        let path = dir.join(name);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents =
+            fs::read(&path).with_context(|| format!("cannot read {}", path.display()))?;
+        Ok(Some(contents))
+    };
+    let headers = match read("headers.json")? {
+        Some(json) => {
+            let json: serde_json::Value =
+                serde_json::from_slice(&json).context("headers.json is not valid JSON")?;
+            headers::from_val(&Val::from(json))?
+        }
+        None => Vec::new(),
+    };
+    let timestamp = match read("timestamp")? {
+        Some(ts) => record::timestamp_from_millis(String::from_utf8_lossy(&ts).trim())?,
+        None => SystemTime::now(),
+    };
+    Ok(InputRecord {
+        key: read("key")?,
+        value: read("value")?,
+        headers,
+        timestamp,
+    })
+}
+
+fn display(path: &Path) -> String {
+    if path == Path::new("-") {
+        "stdin".to_owned()
+    } else {
+        path.display().to_string()
+    }
+}
@@ -0,0 +1,221 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversion between record values and the values filters work on, and validation of those
+//! values.
+
+use std::rc::Rc;
+
+use anyhow::{bail, Context, Result};
+use jaq_interpret::Val;
+use prost_reflect::{DescriptorPool, MessageDescriptor};
+use redpanda_transform_sdk_sr::SchemaRegistryClient;
+
+use crate::avro::{self, AvroSchema};
+use crate::binary;
+use crate::config::{Config, InputFormat, OutputFormat, SchemaSource};
+use crate::json;
+use crate::protobuf::{self, OutputMessage};
+use crate::registry::Registry;
+use crate::validation::JsonSchema;
+
+/// The state the configured formats need, which is looked up when the transform starts.
+pub struct Codec {
+    /// The schema registry, if the formats use it.
+    registry: Option<Registry>,
+    /// The message type of values in the protobuf input format, unless they're in the schema
+    /// registry's wire format.
+    input_message: Option<MessageDescriptor>,
+    /// How outputs are written in the avro and protobuf output formats.
+    output: Option<Output>,
+    /// The JSON Schemas that values are validated with before the filter runs on them, and that
+    /// outputs are validated with before they're written.
+    input_schema: Option<JsonSchema>,
+    output_schema: Option<JsonSchema>,
+}
+
+enum Output {
+    Avro(Rc<AvroSchema>),
+    Protobuf(OutputMessage),
+}
+
+impl Codec {
+    /// Set up the configured formats, where `registry` is `None` if there's no schema registry.
+    pub fn new(config: &Config, registry: Option<SchemaRegistryClient>) -> Result<Self> {
+        let registry = match registry {
+            Some(client) if config.uses_registry() => Some(Registry::new(client)),
+            None if config.uses_registry() => {
+                let user = match (config.input_format, config.output_format) {
+                    (InputFormat::Avro, _) | (_, OutputFormat::Avro) => "the avro format",
+                    (InputFormat::Protobuf, _) if config.input_message.is_none() => {
+                        "the protobuf format"
+                    }
+                    _ if config.output_subject.is_some() => "the protobuf format",
+                    _ if matches!(config.input_schema, Some(SchemaSource::Subject(_))) => {
+                        "INPUT_SCHEMA_SUBJECT"
+                    }
+                    _ => "OUTPUT_SCHEMA_SUBJECT",
+                };
+                bail!("{user} needs a schema registry, which is only available to transforms deployed to Redpanda")
+            }
+            _ => None,
+        };
+        let pool = match &config.descriptor_set {
+            Some(set) => {
+                Some(DescriptorPool::decode(set.as_slice()).context("invalid DESCRIPTOR_SET")?)
+            }
+            None => None,
+        };
+        let message = |var: &str, name: &str| {
+            pool.as_ref()
+                .and_then(|pool| pool.get_message_by_name(name))
+                .with_context(|| format!("the descriptor set has no message {name}, from {var}"))
+        };
+        let input_message = match &config.input_message {
+            Some(name) => Some(message("INPUT_MESSAGE", name)?),
+            None => None,
+        };
+        let output = match (&registry, &config.output_subject) {
+            (Some(registry), Some(subject)) if config.output_format == OutputFormat::Avro => {
+                Some(Output::Avro(
+                    registry
+                        .latest_avro(subject)
+                        .context("cannot look up the schema of OUTPUT_SUBJECT")?,
+                ))
+            }
+            (Some(registry), Some(subject)) => Some(Output::Protobuf(
+                registry
+                    .latest_protobuf(subject)
+                    .and_then(|schema| schema.output(config.output_message.as_deref()))
+                    .context("cannot look up the schema of OUTPUT_SUBJECT")?,
+            )),
+            (_, None) => match &config.output_message {
+                Some(name) => Some(Output::Protobuf(OutputMessage::bare(message(
+                    "OUTPUT_MESSAGE",
+                    name,
+                )?))),
+                None => None,
+            },
+            (None, Some(_)) => None,
+        };
+        let schema = |var: &str, source: &Option<SchemaSource>| match source {
+            Some(SchemaSource::Inline { name, definition }) => Some(
+                JsonSchema::parse(name, definition, &[])
+                    .with_context(|| format!("{name} is not a valid JSON Schema")),
+            ),
+            Some(SchemaSource::Subject(subject)) => Some(
+                registry
+                    .as_ref()
+                    .context("no schema registry")
+                    .and_then(|registry| registry.latest_json(subject))
+                    .with_context(|| format!("cannot look up the schema of {var}_SUBJECT")),
+            ),
+            None => None,
+        };
+        let input_schema = schema("INPUT_SCHEMA", &config.input_schema).transpose()?;
+        let output_schema = schema("OUTPUT_SCHEMA", &config.output_schema).transpose()?;
+        Ok(Self {
+            registry,
+            input_message,
+            output,
+            input_schema,
+            output_schema,
+        })
+    }
+
+    /// Check a value decoded from a record against the input schema, if there is one.
+    pub fn validate_input(&self, value: &Val) -> Result<()> {
+        match &self.input_schema {
+            Some(schema) => schema
+                .validate(value)
+                .with_context(|| format!("value does not match {}", schema.name())),
+            None => Ok(()),
+        }
+    }
+
+    /// Check an output of the filter against the output schema, if there is one.
+    pub fn validate_output(&self, value: &Val) -> Result<()> {
+        match &self.output_schema {
+            Some(schema) => schema
+                .validate(value)
+                .with_context(|| format!("output does not match {}", schema.name())),
+            None => Ok(()),
+        }
+    }
+
+    /// Decode a record value into the inputs of the filter.
+    pub fn decode(&self, config: &Config, value: &[u8]) -> Result<Vec<Val>> {
+        let precise = config.preserve_precision;
+        Ok(match config.input_format {
+            InputFormat::Json => vec![json::parse(value, precise)?],
+            InputFormat::Raw => vec![Val::str(String::from_utf8_lossy(value).into_owned())],
+            InputFormat::Lines => {
+                let lines = String::from_utf8_lossy(value)
+                    .lines()
+                    .map(|line| Val::str(line.to_owned()))
+                    .collect();
+                vec![Val::arr(lines)]
+            }
+            InputFormat::JsonStream => json::parse_stream(value, precise)?,
+            InputFormat::Avro => {
+                let registry = self.registry.as_ref().context("no schema registry")?;
+                vec![avro::decode(
+                    registry,
+                    value,
+                    precise,
+                    config.binary_values,
+                )?]
+            }
+            InputFormat::MessagePack => {
+                vec![binary::read_msgpack(value, precise, config.binary_values)?]
+            }
+            InputFormat::Cbor => vec![binary::read_cbor(value, precise, config.binary_values)?],
+            InputFormat::Protobuf => match &self.input_message {
+                Some(desc) => vec![protobuf::read(desc.clone(), value, precise)?],
+                None => {
+                    let registry = self.registry.as_ref().context("no schema registry")?;
+                    vec![protobuf::decode(registry, value, precise)?]
+                }
+            },
+        })
+    }
+
+    /// Encode an output of the filter as a record value, appending it to `out`.
+    pub fn encode(&self, config: &Config, value: &Val, out: &mut Vec<u8>) -> Result<()> {
+        match (config.output_format, value) {
+            (OutputFormat::Raw | OutputFormat::RawStrict, Val::Str(s)) => {
+                out.extend_from_slice(s.as_bytes());
+                Ok(())
+            }
+            (OutputFormat::RawStrict, value) => {
+                bail!("raw-strict output format only supports strings, got {value}")
+            }
+            (OutputFormat::Json | OutputFormat::Raw, value) => {
+                json::write(out, value, config.preserve_precision)
+            }
+            (OutputFormat::MessagePack, value) => {
+                binary::write_msgpack(out, value, config.preserve_precision, config.binary_values)
+            }
+            (OutputFormat::Cbor, value) => {
+                binary::write_cbor(out, value, config.preserve_precision, config.binary_values)
+            }
+            (OutputFormat::Avro | OutputFormat::Protobuf, value) => {
+                match self.output.as_ref().context("no output schema")? {
+                    Output::Avro(schema) => schema.write(value, out, config.binary_values),
+                    Output::Protobuf(message) => message.write(value, out),
+                }
+            }
+        }
+    }
+}
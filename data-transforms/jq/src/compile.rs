@@ -0,0 +1,185 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compilation of filters and the definitions they use.
+//!
+//! The build script includes this module too, so that filters embedded in the transform at build
+//! time are validated by the build using the same rules as filters passed at runtime. It compiles
+//! them against natives with the names of [`builtins::natives`] that are never run.
+
+use anyhow::{bail, Context, Result};
+use jaq_interpret::{Filter, ParseCtx};
+use jaq_syn::filter::{BinaryOp, Filter as Expr};
+use jaq_syn::{Def, Main};
+
+use crate::{builtins, diagnostic, prelude};
+
+/// The names of the variables holding the record's metadata, in the order their values are returned
+/// by `metadata::to_vals`.
+///
+/// - `$KEY` is the record's key as a string.
+/// - `$HEADERS` is the record's headers as an object.
+/// - `$TOMBSTONE` is true for records without a value.
+/// - `$TIMESTAMP` is the record's timestamp in milliseconds since the Unix epoch, and
+///   `$TIMESTAMP_ISO` the same timestamp as an ISO 8601 string in UTC.
+/// - `$KEY_SIZE` and `$VALUE_SIZE` are the sizes in bytes of the record's key and value.
+/// - `$NOW` is the time that `now` returns in seconds since the Unix epoch, which is the record's
+///   timestamp unless the clock is configured to be the wall clock.
+pub const RECORD_VARS: &[&str] = &[
+    "KEY",
+    "HEADERS",
+    "TOMBSTONE",
+    "TIMESTAMP",
+    "TIMESTAMP_ISO",
+    "KEY_SIZE",
+    "VALUE_SIZE",
+    "NOW",
+];
+
+/// The environment variables as an object, which follows the record's metadata.
+pub const ENV_VAR: &str = "ENV";
+
+/// The names of all variables available to filters: the record's metadata, `$ENV` and then the
+/// named arguments.
+pub fn var_names<'a>(args: impl IntoIterator<Item = &'a str>) -> Result<Vec<String>> {
+    let mut names: Vec<String> = RECORD_VARS.iter().map(|v| v.to_string()).collect();
+    names.push(ENV_VAR.to_owned());
+    for name in args {
+        if names.iter().any(|n| n == name) {
+            bail!("named argument ${name} redefines a builtin variable");
+        }
+        names.push(name.to_owned());
+    }
+    Ok(names)
+}
+
+/// Compile a filter with the given variables, after the definitions of each module in order.
+///
+/// Modules and the filter are `(name, source)` pairs, where the name is used to report errors.
+/// `precise` is whether the filter runs in precise mode, which has [`prelude::PRECISE_DEFS`], and
+/// `steps` whether it counts its steps for `MAX_STEPS`, see [`count_steps`].
+pub fn compile(
+    names: &[String],
+    modules: &[(String, String)],
+    (name, source): (&str, &str),
+    precise: bool,
+    steps: bool,
+) -> Result<Filter> {
+    let mut defs = Vec::new();
+    for (name, source) in modules {
+        let (module, errs) = jaq_parse::parse(source, jaq_parse::defs());
+        if !errs.is_empty() {
+            bail!(
+                "{name} is invalid:\n{}",
+                diagnostic::parse_errors(name, source, &errs)
+            );
+        }
+        let module: Vec<Def> = module
+            .unwrap_or_default()
+            .into_iter()
+            .map(|def| count_steps(def, steps))
+            .collect();
+        // Compile each module on its own first, so that errors are reported against its source.
+        let mut ctx = parse_ctx(names, &defs, precise, steps);
+        ctx.compile(Main {
+            defs: module.clone(),
+            body: (Expr::Id, 0..0),
+        });
+        if !ctx.errs.is_empty() {
+            bail!(
+                "{name} is invalid:\n{}",
+                diagnostic::compile_errors(name, source, &ctx.errs)
+            );
+        }
+        defs.extend(module);
+    }
+    let mut ctx = parse_ctx(names, &defs, precise, steps);
+    let (f, errs) = jaq_parse::parse(source, jaq_parse::main());
+    if !errs.is_empty() {
+        bail!(
+            "filter is invalid:\n{}",
+            diagnostic::parse_errors(name, source, &errs)
+        );
+    }
+    let f = f.context("filter is empty")?;
+    let f = Main {
+        defs: f
+            .defs
+            .into_iter()
+            .map(|def| count_steps(def, steps))
+            .collect(),
+        body: f.body,
+    };
+    let filter = ctx.compile(f);
+    if !ctx.errs.is_empty() {
+        bail!(
+            "filter is invalid:\n{}",
+            diagnostic::compile_errors(name, source, &ctx.errs)
+        );
+    }
+    Ok(filter)
+}
+
+/// A context for compiling filters with the jq standard library, our builtins, the given variables
+/// and the definitions of the configured modules.
+fn parse_ctx(names: &[String], modules: &[Def], precise: bool, steps: bool) -> ParseCtx {
+    let mut ctx = ParseCtx::new(names.to_vec());
+    ctx.insert_natives(builtins::natives());
+    if steps {
+        ctx.insert_defs(builtin_defs(prelude::NATIVE_DEFS, steps));
+    }
+    ctx.insert_defs(
+        jaq_std::std()
+            .into_iter()
+            .map(|def| count_steps(def, steps)),
+    );
+    if precise {
+        ctx.insert_defs(builtin_defs(prelude::PRECISE_DEFS, steps));
+    }
+    assert!(ctx.errs.is_empty()); // These are builtins it should always be valid.
+    ctx.insert_defs(modules.iter().cloned());
+    ctx
+}
+
+fn builtin_defs(source: &str, steps: bool) -> impl Iterator<Item = Def> {
+    let (defs, errs) = jaq_parse::parse(source, jaq_parse::defs());
+    assert!(errs.is_empty());
+    defs.unwrap_or_default()
+        .into_iter()
+        .map(move |def| count_steps(def, steps))
+}
+
+/// Make each call of a definition take a step, so that filters that recurse forever, including
+/// with `repeat`, `while` and `recurse` from the standard library, are stopped by `MAX_STEPS`.
+///
+/// Without `steps` the definition is left as it is, so that filters without a limit don't pay for
+/// counting.
+fn count_steps(def: Def, steps: bool) -> Def {
+    if !steps {
+        return def;
+    }
+    let Main { defs, body } = def.rhs;
+    let span = body.1.start..body.1.start;
+    let step = (Expr::Call(prelude::STEP.to_owned(), Vec::new()), span);
+    Def {
+        lhs: def.lhs,
+        rhs: Main {
+            defs: defs
+                .into_iter()
+                .map(|def| count_steps(def, steps))
+                .collect(),
+            body: Expr::binary(step, BinaryOp::Pipe(None), body),
+        },
+    }
+}
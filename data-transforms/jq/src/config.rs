@@ -0,0 +1,561 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Configuration of the transform, which is passed in as environment variables using
+//! `rpk transform deploy --var=NAME=VALUE`.
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use crate::embedded;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The jq program to run on each record.
+    pub filter: String,
+    /// Where the filter is from, to report errors in it: `FILTER`, or the path of the file it was
+    /// embedded from at build time.
+    pub filter_name: String,
+    /// jq definitions compiled before the filter so it can call them, as `(name, source)` pairs.
+    /// These are the definitions embedded at build time, `FILTER_DEFS` and then each variable named
+    /// `FILTER_MODULE_<NAME>` in order of name, and each can call the definitions before it.
+    pub modules: Vec<(String, String)>,
+    /// Filters that also run on each record, writing their outputs to a topic of their own. These
+    /// are from variables named `ROUTE_<N>_FILTER` and `ROUTE_<N>_TOPIC`, in order of `<N>`.
+    pub routes: Vec<Route>,
+    /// When set `.` is the whole record (key, value, headers and timestamp) instead of only its
+    /// value, and the filter outputs records in the same shape.
+    pub envelope: bool,
+    /// Whether records written by the filter keep the input record's headers, unless the filter
+    /// sets headers itself.
+    pub preserve_headers: bool,
+    /// What to do with records that cannot be decoded, fail the filter or produce outputs that
+    /// cannot be encoded.
+    pub error_policy: ErrorPolicy,
+    /// What to do with records without a value, such as tombstones in compacted topics.
+    pub tombstones: Tombstones,
+    /// How record values are read into the filter's input.
+    pub input_format: InputFormat,
+    /// How the filter's outputs are written as record values.
+    pub output_format: OutputFormat,
+    /// The subject whose latest schema outputs are written with in the `avro` and `protobuf`
+    /// output formats.
+    pub output_subject: Option<String>,
+    /// A serialized `FileDescriptorSet` holding the message types of the `protobuf` formats, which
+    /// is embedded at build time or passed in base64 as `DESCRIPTOR_SET`.
+    pub descriptor_set: Option<Vec<u8>>,
+    /// The fully qualified name of the message type that values are in the `protobuf` input
+    /// format, or `None` when they're in the schema registry's wire format.
+    pub input_message: Option<String>,
+    /// The fully qualified name of the message type that outputs are written as in the `protobuf`
+    /// output format. With an output subject this is a message of its schema, which defaults to
+    /// the first, and otherwise a message of the descriptor set.
+    pub output_message: Option<String>,
+    /// How binary strings of the `msgpack` and `cbor` formats are represented in JSON.
+    pub binary_values: BinaryValues,
+    /// The JSON Schema that values must match before the filter runs on them.
+    pub input_schema: Option<SchemaSource>,
+    /// The JSON Schema that outputs of the filter and its routes must match before they're written.
+    pub output_schema: Option<SchemaSource>,
+    /// What to do with records whose value or outputs don't match their schema, which defaults to
+    /// the error policy. `passthrough` is only available through the error policy.
+    pub validation_policy: ErrorPolicy,
+    /// Whether JSON numbers keep the text they were written with and objects keep the order of
+    /// their fields, instead of numbers being read as doubles and fields being sorted.
+    pub preserve_precision: bool,
+    /// How much work the filter can do for each record.
+    pub limits: Limits,
+    /// The time that `now` and `$NOW` are for each record.
+    pub clock: Clock,
+    /// Regular expressions whose matches `redact_patterns` replaces, as `(name, pattern)` pairs
+    /// from variables named `REDACT_PATTERN_<NAME>` in order of name.
+    pub redact_patterns: Vec<(String, String)>,
+    /// Named arguments for the filter, from variables named `JQ_ARG_<NAME>` holding strings and
+    /// `JQ_ARGJSON_<NAME>` holding JSON. Each is available to the filter as `$NAME`.
+    pub args: Vec<(String, serde_json::Value)>,
+    /// All environment variables, available to the filter as `$ENV`.
+    pub env: BTreeMap<String, String>,
+}
+
+/// A filter whose outputs are written to `topic` instead of the transform's output topic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    /// The variable the filter is from, `ROUTE_<N>_FILTER`, to report errors in it.
+    pub name: String,
+    pub filter: String,
+    pub topic: String,
+}
+
+/// Limits on the filter for each input record, so that a runaway filter such as `repeat(.)` fails
+/// the record instead of running forever or writing without bound. `None` is unlimited, which is
+/// the default so that existing filters keep working.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The most outputs the filter can produce.
+    pub max_outputs: Option<usize>,
+    /// The most bytes the keys and values of the written records can add up to.
+    pub max_output_bytes: Option<usize>,
+    /// The most steps the filter can take, where a step is a call of a definition, including those
+    /// of the standard library, or an output of `range`.
+    pub max_steps: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tombstones {
+    /// The record is handled by the error policy.
+    Error,
+    /// Write the record to the output topic unchanged.
+    Passthrough,
+    /// Drop the record.
+    Drop,
+    /// Run the filter with a `null` value and `$TOMBSTONE` set to `true`.
+    Filter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    /// The value is a single JSON document.
+    Json,
+    /// The value is a string, like `jq -R`.
+    Raw,
+    /// The value is an array of strings, one for each line.
+    Lines,
+    /// The value is a stream of JSON documents, which are concatenated or separated by whitespace
+    /// such as in JSON Lines. The filter runs on each document.
+    JsonStream,
+    /// The value is Avro in the schema registry's wire format, which is read as JSON.
+    Avro,
+    /// The value is a Protobuf message, either of the input message type or in the schema
+    /// registry's wire format, which is read as JSON.
+    Protobuf,
+    /// The value is MessagePack, which is read as JSON.
+    MessagePack,
+    /// The value is CBOR, which is read as JSON.
+    Cbor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Every output is written as JSON.
+    Json,
+    /// Strings are written as raw UTF-8 like `jq -r`, other outputs are written as JSON.
+    Raw,
+    /// Strings are written as raw UTF-8, other outputs are an error.
+    RawStrict,
+    /// Every output is written as Avro in the schema registry's wire format, using the latest
+    /// schema of the output subject.
+    Avro,
+    /// Every output is written as a message of the output message type, in the schema registry's
+    /// wire format when there's an output subject.
+    Protobuf,
+    /// Every output is written as MessagePack.
+    MessagePack,
+    /// Every output is written as CBOR.
+    Cbor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryValues {
+    /// Binary strings are `{"base64": "..."}` objects, and outputs of that shape are written as
+    /// binary strings.
+    Object,
+    /// Binary strings are base64 strings, and outputs are only written as text strings.
+    String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clock {
+    /// The record's timestamp, so that transforming the same records again gives the same outputs.
+    Record,
+    /// The time the record is transformed at.
+    Wall,
+}
+
+/// Where a JSON Schema is from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaSource {
+    /// A schema embedded at build time or passed in a variable, where `name` is the path of the
+    /// file it was embedded from or the variable, to report values that don't match it.
+    Inline { name: String, definition: String },
+    /// The latest schema of a subject in the schema registry.
+    Subject(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Stop processing, the transform retries the record until it's redeployed.
+    Fail,
+    /// Drop the record.
+    Skip,
+    /// Write the record to the output topic unchanged.
+    Passthrough,
+    /// Write the record unchanged to the given topic, with headers describing the error.
+    DeadLetter(String),
+}
+
+impl Config {
+    /// Read the configuration from the process' environment variables.
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(std::env::vars())
+    }
+
+    /// Read the configuration from a set of `(name, value)` pairs.
+    pub fn from_vars(vars: impl IntoIterator<Item = (String, String)>) -> Result<Self> {
+        let vars: BTreeMap<String, String> = vars.into_iter().collect();
+        let runtime_modules = vars
+            .iter()
+            .filter(|(name, _)| *name == "FILTER_DEFS")
+            .chain(
+                vars.iter()
+                    .filter(|(name, _)| name.starts_with("FILTER_MODULE_")),
+            )
+            .map(|(name, source)| (name.clone(), source.clone()));
+        let mut modules: Vec<(String, String)> = embedded::MODULES
+            .iter()
+            .map(|(name, source)| (name.to_string(), source.to_string()))
+            .collect();
+        let routes = parse_routes(&vars)?;
+        let (filter_name, filter) = match embedded::FILTER {
+            // The embedded filter was validated by the build, so it cannot be changed at runtime.
+            Some((name, source)) => {
+                if let Some((var, _)) = vars.iter().find(|(var, _)| {
+                    *var == "FILTER"
+                        || *var == "FILTER_DEFS"
+                        || var.starts_with("FILTER_MODULE_")
+                        || var.starts_with("ROUTE_")
+                }) {
+                    bail!("environment variable {var} cannot be set, the filter is embedded in the transform");
+                }
+                (name.to_owned(), source.to_owned())
+            }
+            None => {
+                let filter = match vars.get("FILTER") {
+                    Some(filter) => filter.clone(),
+                    // With only routes, nothing is written to the output topic.
+                    None if !routes.is_empty() => "empty".to_owned(),
+                    None => bail!("environment variable FILTER is required"),
+                };
+                modules.extend(runtime_modules);
+                ("FILTER".to_owned(), filter)
+            }
+        };
+        let envelope = parse_bool(&vars, "ENVELOPE")?.unwrap_or(false);
+        let preserve_headers = parse_bool(&vars, "PRESERVE_HEADERS")?.unwrap_or(true);
+        let preserve_precision = parse_bool(&vars, "PRESERVE_PRECISION")?.unwrap_or(false);
+        let limits = Limits {
+            max_outputs: parse_limit(&vars, "MAX_OUTPUTS")?,
+            max_output_bytes: parse_limit(&vars, "MAX_OUTPUT_BYTES")?,
+            max_steps: parse_limit(&vars, "MAX_STEPS")?,
+        };
+        let dead_letter_topic = |policy: &str| {
+            vars.get("DEAD_LETTER_TOPIC").cloned().with_context(|| {
+                format!("environment variable DEAD_LETTER_TOPIC is required for the dead-letter {policy}")
+            })
+        };
+        let error_policy = match vars.get("ERROR_POLICY").map(String::as_str) {
+            None | Some("fail") => ErrorPolicy::Fail,
+            Some("skip") => ErrorPolicy::Skip,
+            Some("passthrough") => ErrorPolicy::Passthrough,
+            Some("dead-letter") => ErrorPolicy::DeadLetter(dead_letter_topic("ERROR_POLICY")?),
+            Some(other) => bail!(
+                "environment variable ERROR_POLICY must be fail, skip, passthrough or dead-letter, got {other:?}"
+            ),
+        };
+        let validation_policy = match vars.get("VALIDATION_POLICY").map(String::as_str) {
+            None => error_policy.clone(),
+            Some("fail") => ErrorPolicy::Fail,
+            Some("drop") => ErrorPolicy::Skip,
+            Some("dead-letter") => {
+                ErrorPolicy::DeadLetter(dead_letter_topic("VALIDATION_POLICY")?)
+            }
+            Some(other) => bail!(
+                "environment variable VALIDATION_POLICY must be fail, drop or dead-letter, got {other:?}"
+            ),
+        };
+        let input_schema = parse_schema(&vars, "INPUT_SCHEMA", embedded::INPUT_SCHEMA)?;
+        let output_schema = parse_schema(&vars, "OUTPUT_SCHEMA", embedded::OUTPUT_SCHEMA)?;
+        let tombstones = match vars.get("TOMBSTONES").map(String::as_str) {
+            // Envelopes can represent records without a value, so run the filter on them.
+            None if envelope => Tombstones::Filter,
+            None | Some("error") => Tombstones::Error,
+            Some("passthrough") => Tombstones::Passthrough,
+            Some("drop") => Tombstones::Drop,
+            Some("filter") => Tombstones::Filter,
+            Some(other) => bail!(
+                "environment variable TOMBSTONES must be error, passthrough, drop or filter, got {other:?}"
+            ),
+        };
+        let input_format = match vars.get("INPUT_FORMAT").map(String::as_str) {
+            None | Some("json") => InputFormat::Json,
+            Some("raw") => InputFormat::Raw,
+            Some("lines") => InputFormat::Lines,
+            Some("json-stream") => InputFormat::JsonStream,
+            Some("avro") => InputFormat::Avro,
+            Some("protobuf") => InputFormat::Protobuf,
+            Some("msgpack") => InputFormat::MessagePack,
+            Some("cbor") => InputFormat::Cbor,
+            Some(other) => bail!(
+                "environment variable INPUT_FORMAT must be json, raw, lines, json-stream, avro, protobuf, msgpack or cbor, got {other:?}"
+            ),
+        };
+        let output_format = match vars.get("OUTPUT_FORMAT").map(String::as_str) {
+            None | Some("json") => OutputFormat::Json,
+            Some("raw") => OutputFormat::Raw,
+            Some("raw-strict") => OutputFormat::RawStrict,
+            Some("avro") => OutputFormat::Avro,
+            Some("protobuf") => OutputFormat::Protobuf,
+            Some("msgpack") => OutputFormat::MessagePack,
+            Some("cbor") => OutputFormat::Cbor,
+            Some(other) => bail!(
+                "environment variable OUTPUT_FORMAT must be json, raw, raw-strict, avro, protobuf, msgpack or cbor, got {other:?}"
+            ),
+        };
+        let binary_values = match vars.get("BINARY_VALUES").map(String::as_str) {
+            None | Some("object") => BinaryValues::Object,
+            Some("string") => BinaryValues::String,
+            Some(other) => {
+                bail!("environment variable BINARY_VALUES must be object or string, got {other:?}")
+            }
+        };
+        let output_subject = match output_format {
+            OutputFormat::Avro => Some(vars.get("OUTPUT_SUBJECT").cloned().context(
+                "environment variable OUTPUT_SUBJECT is required for the avro OUTPUT_FORMAT",
+            )?),
+            OutputFormat::Protobuf => vars.get("OUTPUT_SUBJECT").cloned(),
+            _ => None,
+        };
+        let descriptor_set = match (embedded::DESCRIPTOR_SET, vars.get("DESCRIPTOR_SET")) {
+            // Like the filter, the embedded descriptor set was validated by the build.
+            (Some(_), Some(_)) => bail!(
+                "environment variable DESCRIPTOR_SET cannot be set, a descriptor set is embedded in the transform"
+            ),
+            (Some(set), None) => Some(set.to_vec()),
+            (None, Some(set)) => Some(
+                STANDARD
+                    .decode(set.trim())
+                    .context("environment variable DESCRIPTOR_SET is not valid base64")?,
+            ),
+            (None, None) => None,
+        };
+        let input_message = match input_format {
+            InputFormat::Protobuf => vars.get("INPUT_MESSAGE").cloned(),
+            _ => None,
+        };
+        let output_message = match output_format {
+            OutputFormat::Protobuf => vars.get("OUTPUT_MESSAGE").cloned(),
+            _ => None,
+        };
+        if output_format == OutputFormat::Protobuf
+            && output_subject.is_none()
+            && output_message.is_none()
+        {
+            bail!("environment variable OUTPUT_MESSAGE or OUTPUT_SUBJECT is required for the protobuf OUTPUT_FORMAT");
+        }
+        let bare_messages =
+            input_message.is_some() || (output_message.is_some() && output_subject.is_none());
+        if bare_messages && descriptor_set.is_none() {
+            bail!("environment variable DESCRIPTOR_SET is required for the message types of the protobuf formats, unless one is embedded");
+        }
+        let clock = match vars.get("CLOCK").map(String::as_str) {
+            None | Some("record") => Clock::Record,
+            Some("wall") => Clock::Wall,
+            Some(other) => {
+                bail!("environment variable CLOCK must be record or wall, got {other:?}")
+            }
+        };
+        let redact_patterns = vars
+            .iter()
+            .filter(|(name, _)| name.starts_with("REDACT_PATTERN_"))
+            .map(|(name, pattern)| (name.clone(), pattern.clone()))
+            .collect();
+        let mut args = Vec::new();
+        for (name, value) in &vars {
+            let arg = if let Some(arg) = name.strip_prefix("JQ_ARG_") {
+                (arg, serde_json::Value::String(value.clone()))
+            } else if let Some(arg) = name.strip_prefix("JQ_ARGJSON_") {
+                let json = serde_json::from_str(value)
+                    .with_context(|| format!("environment variable {name} is not valid JSON"))?;
+                (arg, json)
+            } else {
+                continue;
+            };
+            if !is_identifier(arg.0) {
+                bail!("environment variable {name} does not name a valid jq variable");
+            }
+            if args.iter().any(|(a, _)| a == arg.0) {
+                bail!(
+                    "environment variable {name} redefines the variable ${}",
+                    arg.0
+                );
+            }
+            args.push((arg.0.to_owned(), arg.1));
+        }
+        for arg in embedded::ARGS {
+            if !args.iter().any(|(a, _)| a == arg) {
+                bail!("environment variable JQ_ARG_{arg} or JQ_ARGJSON_{arg} is required by the embedded filter");
+            }
+        }
+        Ok(Self {
+            filter,
+            filter_name,
+            modules,
+            routes,
+            envelope,
+            preserve_headers,
+            error_policy,
+            tombstones,
+            input_format,
+            output_format,
+            output_subject,
+            descriptor_set,
+            input_message,
+            output_message,
+            binary_values,
+            input_schema,
+            output_schema,
+            validation_policy,
+            preserve_precision,
+            limits,
+            clock,
+            redact_patterns,
+            args,
+            env: vars,
+        })
+    }
+
+    /// Whether the input or output format reads or writes values with schemas from the schema
+    /// registry, or values are validated with schemas from it.
+    pub fn uses_registry(&self) -> bool {
+        let input = match self.input_format {
+            InputFormat::Avro => true,
+            InputFormat::Protobuf => self.input_message.is_none(),
+            _ => false,
+        };
+        let subject =
+            |schema: &Option<SchemaSource>| matches!(schema, Some(SchemaSource::Subject(_)));
+        input
+            || self.output_subject.is_some()
+            || subject(&self.input_schema)
+            || subject(&self.output_schema)
+    }
+}
+
+/// Read the routes from pairs of `ROUTE_<N>_FILTER` and `ROUTE_<N>_TOPIC` variables, ordered by
+/// `<N>`, which is compared as a number if it is one.
+fn parse_routes(vars: &BTreeMap<String, String>) -> Result<Vec<Route>> {
+    let route = |name: &str, suffix| {
+        name.strip_prefix("ROUTE_")
+            .and_then(|name| name.strip_suffix(suffix))
+            .map(str::to_owned)
+    };
+    let mut routes = Vec::new();
+    for name in vars.keys() {
+        if let Some(n) = route(name, "_TOPIC") {
+            if !vars.contains_key(&format!("ROUTE_{n}_FILTER")) {
+                bail!("environment variable ROUTE_{n}_FILTER is required for {name}");
+            }
+        }
+        let Some(n) = route(name, "_FILTER") else {
+            continue;
+        };
+        let topic = vars
+            .get(&format!("ROUTE_{n}_TOPIC"))
+            .filter(|topic| !topic.is_empty())
+            .with_context(|| {
+                format!("environment variable ROUTE_{n}_TOPIC is required for {name}")
+            })?;
+        routes.push((
+            n,
+            Route {
+                name: name.clone(),
+                filter: vars[name].clone(),
+                topic: topic.clone(),
+            },
+        ));
+    }
+    routes.sort_by_cached_key(|(n, _)| (n.parse::<u64>().unwrap_or(u64::MAX), n.clone()));
+    Ok(routes.into_iter().map(|(_, route)| route).collect())
+}
+
+/// Read a JSON Schema from the variable `var`, the subject in `<var>_SUBJECT` or the schema
+/// embedded at build time, only one of which can be given.
+fn parse_schema(
+    vars: &BTreeMap<String, String>,
+    var: &str,
+    embedded: Option<(&str, &str)>,
+) -> Result<Option<SchemaSource>> {
+    let subject_var = format!("{var}_SUBJECT");
+    if embedded.is_some() {
+        // Like the filter, the embedded schema was validated by the build.
+        if let Some(var) = [var, &subject_var]
+            .into_iter()
+            .find(|v| vars.contains_key(*v))
+        {
+            bail!(
+                "environment variable {var} cannot be set, a schema is embedded in the transform"
+            );
+        }
+    }
+    Ok(match (vars.get(var), vars.get(&subject_var)) {
+        (Some(_), Some(_)) => {
+            bail!("environment variables {var} and {subject_var} cannot both be set")
+        }
+        (Some(definition), None) => Some(SchemaSource::Inline {
+            name: var.to_owned(),
+            definition: definition.clone(),
+        }),
+        (None, Some(subject)) => Some(SchemaSource::Subject(subject.clone())),
+        (None, None) => embedded.map(|(name, definition)| SchemaSource::Inline {
+            name: name.to_owned(),
+            definition: definition.to_owned(),
+        }),
+    })
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_bool(vars: &BTreeMap<String, String>, name: &str) -> Result<Option<bool>> {
+    let Some(value) = vars.get(name) else {
+        return Ok(None);
+    };
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "1" => Ok(Some(true)),
+        "false" | "0" => Ok(Some(false)),
+        _ => bail!("environment variable {name} must be true or false, got {value:?}"),
+    }
+}
+
+/// Limits are unlimited unless they are set, and setting them to `0` also removes them.
+fn parse_limit<T: FromStr + Default + PartialEq>(
+    vars: &BTreeMap<String, String>,
+    name: &str,
+) -> Result<Option<T>> {
+    let Some(value) = vars.get(name) else {
+        return Ok(None);
+    };
+    let limit: T = value.parse().ok().with_context(|| {
+        format!("environment variable {name} must be a non-negative integer, got {value:?}")
+    })?;
+    Ok(Some(limit).filter(|l| *l != T::default()))
+}
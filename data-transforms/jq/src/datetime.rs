@@ -0,0 +1,300 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Functions of filters on dates and times.
+//!
+//! Times are numbers of seconds since the Unix epoch like those of jq's `now` and `todate`, with
+//! fractions of a second kept to the microsecond. Functions that take a time also take an RFC 3339
+//! string, which keeps nanoseconds. Time zones are IANA names such as `Europe/Berlin`, `UTC` or
+//! fixed offsets such as `+05:30`, from the time zone database built into the transform.
+//!
+//! `now` is the time of the record being transformed rather than of the clock, unless configured
+//! otherwise, so that transforming the same records again gives the same outputs.
+
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use jaq_interpret::results::box_once;
+use jaq_interpret::{Error, FilterT, RunPtr, Val, ValR};
+use jiff::fmt::strtime;
+use jiff::tz::{Offset, TimeZone};
+use jiff::{RoundMode, Timestamp, Unit, Zoned, ZonedRound};
+
+/// The natives, which replace jaq's `now`.
+pub const NATIVES: &[(&str, usize, RunPtr)] = &[
+    ("now", 0, |_, _| box_once(Ok(now()))),
+    ("from_rfc3339", 0, |_, cv| {
+        box_once(cv.1.as_str().and_then(|s| parse_rfc3339(s)).map(seconds))
+    }),
+    ("to_rfc3339", 0, |_, cv| {
+        box_once(timestamp(&cv.1).map(|ts| Val::str(ts.to_string())))
+    }),
+    ("to_rfc3339", 1, |args, cv| {
+        Box::new(
+            args.get(0)
+                .run(cv.clone())
+                .map(move |tz| to_rfc3339(&cv.1, &tz?)),
+        )
+    }),
+    ("parse_time", 1, |args, cv| {
+        Box::new(
+            args.get(0)
+                .run(cv.clone())
+                .map(move |fmt| parse_time(&cv.1, &fmt?, None)),
+        )
+    }),
+    ("parse_time", 2, |args, cv| {
+        Box::new(
+            args.get(0)
+                .cartesian(args.get(1), cv.clone())
+                .map(move |(fmt, tz)| parse_time(&cv.1, &fmt?, Some(&tz?))),
+        )
+    }),
+    ("format_time", 1, |args, cv| {
+        Box::new(
+            args.get(0)
+                .run(cv.clone())
+                .map(move |fmt| format_time(&cv.1, &fmt?, None)),
+        )
+    }),
+    ("format_time", 2, |args, cv| {
+        Box::new(
+            args.get(0)
+                .cartesian(args.get(1), cv.clone())
+                .map(move |(fmt, tz)| format_time(&cv.1, &fmt?, Some(&tz?))),
+        )
+    }),
+    ("from_epoch", 1, |args, cv| {
+        Box::new(
+            args.get(0)
+                .run(cv.clone())
+                .map(move |unit| from_epoch(&cv.1, &unit?)),
+        )
+    }),
+    ("to_epoch", 1, |args, cv| {
+        Box::new(
+            args.get(0)
+                .run(cv.clone())
+                .map(move |unit| to_epoch(&cv.1, &unit?)),
+        )
+    }),
+    ("truncate_time", 1, |args, cv| {
+        Box::new(
+            args.get(0)
+                .run(cv.clone())
+                .map(move |unit| truncate_time(&cv.1, &unit?, None)),
+        )
+    }),
+    ("truncate_time", 2, |args, cv| {
+        Box::new(
+            args.get(0)
+                .cartesian(args.get(1), cv.clone())
+                .map(move |(unit, tz)| truncate_time(&cv.1, &unit?, Some(&tz?))),
+        )
+    }),
+];
+
+thread_local! {
+    /// The time of `now`, which is that of the record being transformed.
+    static NOW: Cell<Timestamp> = const { Cell::new(Timestamp::UNIX_EPOCH) };
+}
+
+/// Make `now` the time that `now` and `$NOW` return for the filter that runs next.
+pub fn set_now(now: SystemTime) {
+    // Times outside of the years -9999 to 9999 are clamped to the nearest one.
+    let now = Timestamp::try_from(now).unwrap_or_else(|_| {
+        match now.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(_) => Timestamp::MAX,
+            Err(_) => Timestamp::MIN,
+        }
+    });
+    NOW.set(now);
+}
+
+/// The time set by [`set_now`] in seconds since the Unix epoch.
+pub fn now() -> Val {
+    seconds(NOW.get())
+}
+
+/// The time of a number of seconds since the Unix epoch or an RFC 3339 string.
+fn timestamp(val: &Val) -> Result<Timestamp, Error> {
+    let ts = match val {
+        Val::Int(s) => Timestamp::from_second(*s as i64),
+        Val::Float(s) => from_float(*s, 1e6),
+        Val::Num(n) => match n.parse::<i64>() {
+            Ok(s) => Timestamp::from_second(s),
+            Err(_) => from_float(n.parse().unwrap_or(f64::NAN), 1e6),
+        },
+        Val::Str(s) => return parse_rfc3339(s),
+        _ => {
+            return Err(Error::str(format_args!(
+                "expected a number of seconds or an RFC 3339 string, got {val}"
+            )))
+        }
+    };
+    ts.map_err(|e| Error::str(format_args!("cannot read {val} as a time: {e}")))
+}
+
+/// The time of `n` units, which are `micros` microseconds, since the Unix epoch, to the nearest
+/// microsecond. Doubles cannot represent the nanoseconds of current times.
+fn from_float(n: f64, micros: f64) -> Result<Timestamp, jiff::Error> {
+    let micros = (n * micros).round();
+    // Casts saturate, so infinite and huge numbers are out of range, and so is NaN.
+    Timestamp::from_microsecond(if micros.is_nan() {
+        i64::MAX
+    } else {
+        micros as i64
+    })
+}
+
+/// Seconds since the Unix epoch, which are integers for whole seconds.
+fn seconds(ts: Timestamp) -> Val {
+    match ts.subsec_nanosecond() {
+        0 => int(ts.as_second().into()),
+        nanos => Val::Float(ts.as_second() as f64 + f64::from(nanos) / 1e9),
+    }
+}
+
+/// Integers that don't fit in the 32 bit integers jaq uses on Wasm fall back to its arbitrary
+/// precision numbers, like [`crate::metadata::timestamp_millis`].
+fn int(n: i128) -> Val {
+    isize::try_from(n).map_or_else(|_| Val::Num(Rc::new(n.to_string())), Val::Int)
+}
+
+fn parse_rfc3339(s: &str) -> Result<Timestamp, Error> {
+    s.parse()
+        .map_err(|e| Error::str(format_args!("cannot parse {s:?} as RFC 3339: {e}")))
+}
+
+/// A time zone by IANA name, or a fixed offset such as `+05:30`.
+fn time_zone(tz: &Val) -> Result<TimeZone, Error> {
+    let name = tz.as_str()?;
+    if let Some(offset) = parse_offset(name) {
+        return Ok(TimeZone::fixed(offset));
+    }
+    TimeZone::get(name).map_err(|_| Error::str(format_args!("unknown time zone {name:?}")))
+}
+
+/// Parse an offset of the form `+HH:MM` or `-HH:MM`.
+fn parse_offset(s: &str) -> Option<Offset> {
+    let (sign, hh_mm) = match s.split_at_checked(1)? {
+        ("+", rest) => (1, rest),
+        ("-", rest) => (-1, rest),
+        _ => return None,
+    };
+    let (hh, mm) = hh_mm.split_once(':')?;
+    if hh.len() != 2 || mm.len() != 2 {
+        return None;
+    }
+    let (hh, mm): (i32, i32) = (hh.parse().ok()?, mm.parse().ok()?);
+    if mm >= 60 {
+        return None;
+    }
+    Offset::from_seconds(sign * (hh * 3600 + mm * 60)).ok()
+}
+
+/// The time in the time zone `tz`, or UTC for `None`.
+fn zoned(val: &Val, tz: Option<&Val>) -> Result<Zoned, Error> {
+    let tz = tz.map_or(Ok(TimeZone::UTC), time_zone)?;
+    Ok(timestamp(val)?.to_zoned(tz))
+}
+
+fn to_rfc3339(val: &Val, tz: &Val) -> ValR {
+    let time = zoned(val, Some(tz))?;
+    let ts = time.timestamp();
+    Ok(Val::str(ts.display_with_offset(time.offset()).to_string()))
+}
+
+/// Parse a string with a `strftime` format. Strings without an offset or time zone are times in
+/// `tz`, or UTC for `None`.
+fn parse_time(val: &Val, fmt: &Val, tz: Option<&Val>) -> ValR {
+    let (s, fmt) = (val.as_str()?, fmt.as_str()?);
+    let fail = |e| Error::str(format_args!("cannot parse {s:?} with format {fmt:?}: {e}"));
+    let time = strtime::parse(fmt.as_bytes(), s.as_bytes()).map_err(fail)?;
+    let ts = if time.offset().is_some() || time.iana_time_zone().is_some() {
+        time.to_zoned().map_err(fail)?.timestamp()
+    } else if let Some(ts) = time.timestamp() {
+        ts
+    } else {
+        let tz = tz.map_or(Ok(TimeZone::UTC), time_zone)?;
+        let datetime = time.to_datetime().map_err(fail)?;
+        tz.to_zoned(datetime).map_err(fail)?.timestamp()
+    };
+    Ok(seconds(ts))
+}
+
+fn format_time(val: &Val, fmt: &Val, tz: Option<&Val>) -> ValR {
+    let fmt = fmt.as_str()?;
+    let time = zoned(val, tz)?;
+    strtime::format(fmt.as_bytes(), &time)
+        .map(Val::str)
+        .map_err(|e| Error::str(format_args!("cannot format {val} with format {fmt:?}: {e}")))
+}
+
+/// The number of nanoseconds in a unit of [`from_epoch`] and [`to_epoch`].
+fn epoch_unit(unit: &Val) -> Result<i128, Error> {
+    match unit.as_str()?.as_str() {
+        "s" => Ok(1_000_000_000),
+        "ms" => Ok(1_000_000),
+        "us" => Ok(1_000),
+        "ns" => Ok(1),
+        other => Err(Error::str(format_args!(
+            "unknown unit {other:?}, expected \"s\", \"ms\", \"us\" or \"ns\""
+        ))),
+    }
+}
+
+/// Convert a number of `unit`s since the Unix epoch to seconds.
+fn from_epoch(val: &Val, unit: &Val) -> ValR {
+    let nanos = epoch_unit(unit)?;
+    let ts = match val {
+        Val::Int(n) => Timestamp::from_nanosecond(*n as i128 * nanos),
+        Val::Float(n) => from_float(*n, nanos as f64 / 1e3),
+        Val::Num(n) => match n.parse::<i128>() {
+            Ok(n) => Timestamp::from_nanosecond(n.saturating_mul(nanos)),
+            Err(_) => from_float(n.parse().unwrap_or(f64::NAN), nanos as f64 / 1e3),
+        },
+        _ => return Err(Error::str(format_args!("expected a number, got {val}"))),
+    };
+    ts.map(seconds)
+        .map_err(|e| Error::str(format_args!("cannot read {val} as a time: {e}")))
+}
+
+/// Convert a time to the whole number of `unit`s since the Unix epoch, rounding down.
+fn to_epoch(val: &Val, unit: &Val) -> ValR {
+    let nanos = epoch_unit(unit)?;
+    Ok(int(timestamp(val)?.as_nanosecond().div_euclid(nanos)))
+}
+
+/// Truncate a time to the start of its second, minute, hour or day in `tz`, or UTC for `None`.
+fn truncate_time(val: &Val, unit: &Val, tz: Option<&Val>) -> ValR {
+    let name = unit.as_str()?;
+    let unit = match name.as_str() {
+        "second" => Unit::Second,
+        "minute" => Unit::Minute,
+        "hour" => Unit::Hour,
+        "day" => Unit::Day,
+        other => {
+            return Err(Error::str(format_args!(
+                "unknown unit {other:?}, expected \"second\", \"minute\", \"hour\" or \"day\""
+            )))
+        }
+    };
+    let time = zoned(val, tz)?;
+    let round = ZonedRound::new().smallest(unit).mode(RoundMode::Trunc);
+    time.round(round)
+        .map(|t| seconds(t.timestamp()))
+        .map_err(|e| Error::str(format_args!("cannot truncate {val} to the {name}: {e}")))
+}
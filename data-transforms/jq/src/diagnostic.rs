@@ -0,0 +1,173 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Human readable reports for filters that fail to parse or compile.
+//!
+//! These end up in the transform's logs when `rpk transform deploy` is given a bad filter, so
+//! each error points at the offending line and column and underlines it, like so:
+//!
+//! ```text
+//! error: unexpected "}", expected one of "(", ".", ...
+//!  --> FILTER:1:12
+//!   |
+//! 1 | .foo | map(}
+//!   |            ^
+//! ```
+
+use std::fmt::Write;
+use std::ops::Range;
+
+use chumsky::error::SimpleReason;
+use jaq_syn::filter::Filter as Expr;
+
+/// A single error found in a filter's source, spanning a range of characters.
+struct Diagnostic {
+    span: Range<usize>,
+    message: String,
+}
+
+/// Render errors reported by `jaq_parse::parse` for the source named `name`.
+pub fn parse_errors(name: &str, src: &str, errs: &[jaq_parse::Error]) -> String {
+    let diagnostics: Vec<_> = errs.iter().map(|err| parse_diagnostic(src, err)).collect();
+    render(name, src, &diagnostics)
+}
+
+/// Render errors collected in `ParseCtx::errs` after compiling the source named `name`.
+pub fn compile_errors<E: std::fmt::Display>(
+    name: &str,
+    src: &str,
+    errs: &[jaq_syn::Spanned<E>],
+) -> String {
+    let diagnostics: Vec<_> = errs
+        .iter()
+        .map(|(err, span)| Diagnostic {
+            span: span.clone(),
+            message: compile_message(&err.to_string(), slice(src, span)),
+        })
+        .collect();
+    render(name, src, &diagnostics)
+}
+
+fn parse_diagnostic(src: &str, err: &jaq_parse::Error) -> Diagnostic {
+    let found = match err.found() {
+        Some(tok) => format!("{tok:?}"),
+        None => "end of input".to_owned(),
+    };
+    let mut message = match err.reason() {
+        SimpleReason::Unclosed { span, delimiter } => {
+            let (line_no, column, _) = locate(src, span.start);
+            return Diagnostic {
+                span: err.span(),
+                message: format!(
+                    "unclosed delimiter {delimiter:?} opened at {line_no}:{column}, found {found}"
+                ),
+            };
+        }
+        SimpleReason::Custom(msg) => msg.clone(),
+        SimpleReason::Unexpected => format!("unexpected {found}"),
+    };
+    // The expected set is a hash set, sort it so reports are stable between runs.
+    let mut expected: Vec<_> = err
+        .expected()
+        .map(|tok| match tok {
+            Some(tok) => format!("{tok:?}"),
+            None => "end of input".to_owned(),
+        })
+        .collect();
+    expected.sort();
+    expected.dedup();
+    match expected.as_slice() {
+        [] => {}
+        [one] => write!(message, ", expected {one}").unwrap(),
+        many => write!(message, ", expected one of {}", many.join(", ")).unwrap(),
+    }
+    Diagnostic {
+        span: err.span(),
+        message,
+    }
+}
+
+/// Compile errors only carry a description like "undefined filter" and the span of the offending
+/// expression, so recover the name (and arity for filters) from the source itself.
+fn compile_message(description: &str, snippet: &str) -> String {
+    let (parsed, errs) = jaq_parse::parse(snippet, jaq_parse::main());
+    match parsed.filter(|_| errs.is_empty()).map(|main| main.body.0) {
+        Some(Expr::Call(name, args)) => format!("{description} `{name}/{}`", args.len()),
+        Some(Expr::Var(name)) => format!("{description} `${name}`"),
+        _ => format!("{description} `{snippet}`"),
+    }
+}
+
+fn render(name: &str, src: &str, diagnostics: &[Diagnostic]) -> String {
+    let mut out = String::new();
+    for (i, diagnostic) in diagnostics.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        render_one(&mut out, name, src, diagnostic);
+    }
+    out.truncate(out.trim_end().len());
+    out
+}
+
+fn render_one(out: &mut String, name: &str, src: &str, diagnostic: &Diagnostic) {
+    let (line_no, column, line) = locate(src, diagnostic.span.start);
+    // Underline at least one character, and never past the end of the line.
+    let line_len = line.chars().count();
+    let width = diagnostic
+        .span
+        .len()
+        .min(line_len.saturating_sub(column - 1))
+        .max(1);
+    let gutter = " ".repeat(line_no.to_string().len());
+    writeln!(out, "error: {}", diagnostic.message).unwrap();
+    writeln!(out, "{gutter}--> {name}:{line_no}:{column}").unwrap();
+    writeln!(out, "{gutter} |").unwrap();
+    writeln!(out, "{line_no} | {line}").unwrap();
+    writeln!(
+        out,
+        "{gutter} | {}{}",
+        " ".repeat(column - 1),
+        "^".repeat(width)
+    )
+    .unwrap();
+}
+
+/// Find the 1-based line and column of the character offset `pos` along with the line's text.
+///
+/// jaq's spans count characters rather than bytes, so this does as well.
+fn locate(src: &str, pos: usize) -> (usize, usize, &str) {
+    let mut line_start = 0;
+    let mut line_no = 1;
+    let mut column = 1;
+    for (i, (byte, c)) in src.char_indices().enumerate() {
+        if i == pos {
+            break;
+        }
+        if c == '\n' {
+            line_start = byte + 1;
+            line_no += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+    let line = src[line_start..].lines().next().unwrap_or_default();
+    (line_no, column, line)
+}
+
+fn slice<'a>(src: &'a str, span: &Range<usize>) -> &'a str {
+    let byte = |pos| src.char_indices().nth(pos).map_or(src.len(), |(b, _)| b);
+    &src[byte(span.start)..byte(span.end)]
+}
@@ -0,0 +1,22 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Filters embedded in the transform at build time, see `build.rs`.
+//!
+//! `FILTER` is the embedded filter and `MODULES` the embedded definitions, as `(path, source)`
+//! pairs. `ARGS` are the named arguments the embedded filter was validated with,
+//! `DESCRIPTOR_SET` is the embedded descriptor set of the protobuf formats, and `INPUT_SCHEMA` and
+//! `OUTPUT_SCHEMA` are the embedded JSON Schemas as `(path, source)` pairs.
+
+include!(concat!(env!("OUT_DIR"), "/embedded.rs"));
@@ -0,0 +1,109 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The envelope exposes the whole record to the filter instead of only its value:
+//!
+//! ```json
+//! {"key": "k", "value": {"foo": 42}, "headers": {"tenant": "acme"}, "timestamp": 1707749921393}
+//! ```
+//!
+//! The filter outputs records in the same shape, which lets it rewrite keys and headers along with
+//! the value. See [`crate::headers`] for how headers are represented. Keys are represented the same
+//! way as header values, so binary keys such as Avro keys pass through unchanged.
+
+use std::rc::Rc;
+
+use anyhow::{bail, Result};
+use jaq_interpret::Val;
+use redpanda_transform_sdk::{RecordHeader, WrittenRecord};
+
+use crate::config::Config;
+use crate::metadata::timestamp_millis;
+use crate::{headers, json};
+
+/// A record produced by a filter running in envelope mode.
+pub struct Envelope {
+    /// The topic to write the record to, or `None` for the topic of the filter.
+    pub topic: Option<Rc<String>>,
+    /// The key, which is written with [`encode_key`].
+    pub key: Option<Val>,
+    pub value: Option<Val>,
+    pub headers: Vec<RecordHeader>,
+}
+
+/// Wrap the record and its already decoded `value` into an envelope for the filter's input.
+pub fn to_val(record: &WrittenRecord, value: Val) -> Val {
+    let key = record.key().map_or(Val::Null, headers::bytes_to_val);
+    let timestamp = timestamp_millis(record.timestamp());
+    Val::obj(
+        [
+            ("key", key),
+            ("value", value),
+            ("headers", headers::to_val(record.headers())),
+            ("timestamp", timestamp),
+        ]
+        .into_iter()
+        .map(|(k, v)| (Rc::new(k.to_owned()), v))
+        .collect(),
+    )
+}
+
+/// Unwrap an envelope output by the filter for the record it was produced from.
+///
+/// A missing or `null` field leaves that part of the output record empty. The timestamp cannot be
+/// changed, as records written by a transform are always assigned the input record's timestamp.
+pub fn from_val(val: Val, record: &WrittenRecord) -> Result<Envelope> {
+    let Val::Obj(fields) = val else {
+        bail!("envelope output must be an object, got {val}");
+    };
+    let mut envelope = Envelope {
+        topic: None,
+        key: None,
+        value: None,
+        headers: Vec::new(),
+    };
+    for (name, field) in fields.iter() {
+        match name.as_str() {
+            "topic" => match field {
+                Val::Null => {}
+                Val::Str(topic) => envelope.topic = Some(topic.clone()),
+                _ => bail!("topic must be a string, got {field}"),
+            },
+            "key" if *field == Val::Null => {}
+            "key" => envelope.key = Some(field.clone()),
+            "value" if *field == Val::Null => {}
+            "value" => envelope.value = Some(field.clone()),
+            "headers" => envelope.headers = headers::from_val(field)?,
+            "timestamp" => {
+                let timestamp = timestamp_millis(record.timestamp());
+                if *field != Val::Null && *field != timestamp {
+                    bail!("cannot change the record's timestamp from {timestamp} to {field}");
+                }
+            }
+            _ => bail!("unexpected field {name:?} in envelope output"),
+        }
+    }
+    Ok(envelope)
+}
+
+/// Strings and `{"base64": "..."}` objects are written as their bytes, like header values, while
+/// other values are written as JSON.
+pub fn encode_key(config: &Config, key: &Val, out: &mut Vec<u8>) -> Result<()> {
+    match (key, headers::base64_from_val(key)) {
+        (Val::Str(s), _) => out.extend_from_slice(s.as_bytes()),
+        (_, Some(bytes)) => out.extend_from_slice(&bytes?),
+        (key, None) => json::write(out, key, config.preserve_precision)?,
+    }
+    Ok(())
+}
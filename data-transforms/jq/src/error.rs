@@ -0,0 +1,117 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Errors that occur while processing a single record, which are handled according to the
+//! configured [`crate::config::ErrorPolicy`].
+
+use std::cell::Cell;
+use std::fmt;
+
+/// The step of processing a record that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Turning the input record into the filter's input.
+    Decode,
+    /// Running the filter.
+    Filter,
+    /// Turning the filter's outputs into records.
+    Encode,
+    /// Going over one of the configured [`crate::config::Limits`].
+    Limit,
+    /// Writing the records, such as to a topic that isn't an output topic of the transform.
+    Write,
+    /// Checking the filter's input or outputs against their JSON Schema, which is handled by the
+    /// validation policy instead of the error policy.
+    Validate,
+}
+
+impl Stage {
+    const COUNT: usize = 6;
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Stage::Decode => "decode",
+            Stage::Filter => "filter",
+            Stage::Encode => "encode",
+            Stage::Limit => "limit",
+            Stage::Write => "write",
+            Stage::Validate => "validate",
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub struct TransformError {
+    pub stage: Stage,
+    /// The route whose filter failed, as an index into [`crate::config::Config::routes`], or
+    /// `None` if the error isn't from a route.
+    pub route: Option<usize>,
+    pub error: anyhow::Error,
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} error: {:#}", self.stage, self.error)
+    }
+}
+
+impl std::error::Error for TransformError {}
+
+/// Attach the [`Stage`] an error occurred in.
+pub trait ResultExt<T> {
+    fn stage(self, stage: Stage) -> Result<T, TransformError>;
+}
+
+impl<T, E: Into<anyhow::Error>> ResultExt<T> for Result<T, E> {
+    fn stage(self, stage: Stage) -> Result<T, TransformError> {
+        self.map_err(|e| TransformError {
+            stage,
+            route: None,
+            error: e.into(),
+        })
+    }
+}
+
+/// Counts the records of each [`Stage`] that were skipped or passed through. Only the first error,
+/// and then the error each time the count doubles, is logged, so a producer writing bad records
+/// cannot flood the transform's log.
+#[derive(Default)]
+pub struct ErrorLog {
+    counts: Cell<[u64; Stage::COUNT]>,
+}
+
+impl ErrorLog {
+    /// Count a record that `action`, such as "skipped", was done to because of `err`.
+    pub fn log(&self, action: &str, err: &TransformError) {
+        let mut counts = self.counts.get();
+        counts[err.stage as usize] += 1;
+        self.counts.set(counts);
+        match counts[err.stage as usize] {
+            1 => eprintln!("{action} record, {err}"),
+            n if n.is_power_of_two() => {
+                eprintln!(
+                    "{action} {n} records with {} errors so far, the last with {err}",
+                    err.stage
+                )
+            }
+            _ => {}
+        }
+    }
+}
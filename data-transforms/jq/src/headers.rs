@@ -0,0 +1,97 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conversion of record headers to and from jq objects.
+//!
+//! Headers are represented as an object from header name to value. Values that are valid UTF-8 are
+//! strings, other values are `{"base64": "..."}` objects and headers without a value are `null`.
+//! If a record has the same header more than once, the last value wins.
+
+use std::rc::Rc;
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use jaq_interpret::Val;
+use redpanda_transform_sdk::{BorrowedHeader, RecordHeader};
+
+const BASE64_FIELD: &str = "base64";
+
+pub fn to_val(headers: &[BorrowedHeader]) -> Val {
+    Val::obj(
+        headers
+            .iter()
+            .map(|h| {
+                let name = String::from_utf8_lossy(h.key()).into_owned();
+                (Rc::new(name), h.value().map_or(Val::Null, bytes_to_val))
+            })
+            .collect(),
+    )
+}
+
+pub fn from_val(val: &Val) -> Result<Vec<RecordHeader>> {
+    match val {
+        Val::Null => Ok(Vec::new()),
+        Val::Obj(headers) => headers
+            .iter()
+            .map(|(name, value)| {
+                let value =
+                    bytes_from_val(value).with_context(|| format!("invalid header {name:?}"))?;
+                Ok(RecordHeader::new(name.as_bytes().to_vec(), value))
+            })
+            .collect(),
+        _ => bail!("headers must be an object, got {val}"),
+    }
+}
+
+/// Bytes that are valid UTF-8 are strings, other bytes are `{"base64": "..."}` objects.
+pub fn bytes_to_val(value: &[u8]) -> Val {
+    match std::str::from_utf8(value) {
+        Ok(s) => Val::str(s.to_owned()),
+        Err(_) => Val::obj(
+            [(
+                Rc::new(BASE64_FIELD.to_owned()),
+                Val::str(STANDARD.encode(value)),
+            )]
+            .into_iter()
+            .collect(),
+        ),
+    }
+}
+
+/// The inverse of [`bytes_to_val`], where `null` is no bytes at all.
+pub fn bytes_from_val(val: &Val) -> Result<Option<Vec<u8>>> {
+    Ok(match val {
+        Val::Null => None,
+        Val::Str(s) => Some(s.as_bytes().to_vec()),
+        _ => match base64_from_val(val) {
+            Some(bytes) => Some(bytes?),
+            None => bail!("expected a string, null or {{\"base64\": ...}}, got {val}"),
+        },
+    })
+}
+
+/// The bytes of a `{"base64": "..."}` object, or `None` if `val` isn't one.
+pub fn base64_from_val(val: &Val) -> Option<Result<Vec<u8>>> {
+    let Val::Obj(o) = val else {
+        return None;
+    };
+    match o.iter().next() {
+        Some((k, Val::Str(b64))) if o.len() == 1 && **k == BASE64_FIELD => Some(
+            STANDARD
+                .decode(&***b64)
+                .with_context(|| format!("{b64:?} is not valid base64")),
+        ),
+        _ => None,
+    }
+}
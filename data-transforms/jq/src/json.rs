@@ -0,0 +1,216 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! JSON that is read into and written from filter values directly, without going through
+//! `serde_json::Value`, so that records are only parsed and serialized once.
+//!
+//! By default values are read and written the same as `serde_json` does: numbers are doubles
+//! unless they are integers that fit in 64 bits, and the fields of objects are sorted by name.
+//!
+//! In precise mode numbers keep the text they were written with unless the filter changes them, so
+//! integers that don't fit in a double and decimals like `1.50` are written exactly as they were
+//! read. Objects keep the order of their fields.
+
+use std::io::Write;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use hifijson::num::LexWrite as _;
+use hifijson::token::{Expect, Lex as _};
+use hifijson::{Error, LexAlloc, SliceLexer, Token};
+use jaq_interpret::Val;
+
+/// Objects nested deeper than this are rejected, the same as `serde_json` does, so that a record
+/// cannot overflow the stack.
+pub const MAX_DEPTH: usize = 128;
+
+/// Parse a single JSON document.
+pub fn parse(value: &[u8], precise: bool) -> Result<Val> {
+    SliceLexer::new(value)
+        .exactly_one(|token, lexer| parse_val(token, lexer, precise, 0))
+        .map_err(|e| parse_error(e, serde_json::from_slice::<serde_json::Value>(value).err()))
+}
+
+/// Parse a stream of JSON documents, which are concatenated or separated by whitespace.
+pub fn parse_stream(value: &[u8], precise: bool) -> Result<Vec<Val>> {
+    let mut lexer = SliceLexer::new(value);
+    let mut docs = Vec::new();
+    while let Some(token) = lexer.ws_token() {
+        let doc = parse_val(token, &mut lexer, precise, 0).map_err(|e| {
+            let mut docs =
+                serde_json::Deserializer::from_slice(value).into_iter::<serde_json::Value>();
+            parse_error(e, docs.find_map(Result::err))
+        })?;
+        docs.push(doc);
+    }
+    Ok(docs)
+}
+
+fn parse_val(
+    token: Token,
+    lexer: &mut impl LexAlloc,
+    precise: bool,
+    depth: usize,
+) -> Result<Val, Error> {
+    match token {
+        Token::Null => Ok(Val::Null),
+        Token::True => Ok(Val::Bool(true)),
+        Token::False => Ok(Val::Bool(false)),
+        Token::DigitOrMinus => {
+            let (num, parts) = lexer.num_string()?;
+            number(&num, parts.is_int(), precise).ok_or(Error::Token(Expect::Value))
+        }
+        Token::Quote => Ok(Val::str(lexer.str_string()?.to_string())),
+        Token::LSquare if depth < MAX_DEPTH => {
+            let mut arr = Vec::new();
+            lexer.seq(Token::RSquare, |token, lexer| {
+                arr.push(parse_val(token, lexer, precise, depth + 1)?);
+                Ok::<_, Error>(())
+            })?;
+            Ok(Val::arr(arr))
+        }
+        Token::LCurly if depth < MAX_DEPTH => {
+            let mut fields = Vec::new();
+            lexer.seq(Token::RCurly, |token, lexer| {
+                let key = lexer.str_colon(token, |lexer| lexer.str_string().map_err(Error::Str))?;
+                let token = lexer.ws_token().ok_or(Expect::Value)?;
+                let value = parse_val(token, lexer, precise, depth + 1)?;
+                fields.push((Rc::new(key.to_string()), value));
+                Ok::<_, Error>(())
+            })?;
+            // Sorting is stable, so the last of duplicate fields still wins.
+            if !precise {
+                fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+            }
+            Ok(Val::obj(fields.into_iter().collect()))
+        }
+        Token::LSquare | Token::LCurly => Err(Error::Depth),
+        _ => Err(Expect::Value)?,
+    }
+}
+
+/// Read a number from its JSON text, where `integer` is whether it has neither a fraction nor an
+/// exponent.
+///
+/// Integers that fit in an `isize` are read as integers. Other numbers keep their text in precise
+/// mode, and are otherwise normalized to how `serde_json` writes them, or `None` if they're out of
+/// range. `-0` is a double to `serde_json`, so it isn't an integer either.
+pub fn number(num: &str, integer: bool, precise: bool) -> Option<Val> {
+    if integer && num != "-0" {
+        if let Ok(i) = num.parse() {
+            return Some(Val::Int(i));
+        }
+    }
+    if precise {
+        return Some(Val::Num(Rc::new(num.to_owned())));
+    }
+    let num = normalize(&serde_json::Number::from_str(num).ok()?);
+    Some(
+        num.parse()
+            .map_or_else(|_| Val::Num(Rc::new(num)), Val::Int),
+    )
+}
+
+/// The lexer doesn't report where errors are, so use `serde_json` to report them with their line
+/// and column, the same as when values are read with `serde_json`.
+fn parse_error(err: Error, located: Option<serde_json::Error>) -> anyhow::Error {
+    match located {
+        Some(e) => e.into(),
+        None => anyhow!("{err}"),
+    }
+}
+
+/// Write a value as JSON, appending it to `out`.
+pub fn write(out: &mut Vec<u8>, value: &Val, precise: bool) -> Result<()> {
+    match value {
+        Val::Null => out.extend_from_slice(b"null"),
+        Val::Bool(b) => write!(out, "{b}")?,
+        Val::Int(i) => write!(out, "{i}")?,
+        Val::Float(f) => write_float(out, *f),
+        // Numbers are either read from the input or literals in the filter, but only write them as
+        // is if they are valid JSON, even when they're out of the range of a double.
+        Val::Num(n) if precise && is_number(n) => out.extend_from_slice(n.as_bytes()),
+        Val::Num(n) => match serde_json::Number::from_str(n) {
+            Ok(n) => out.extend_from_slice(normalize(&n).as_bytes()),
+            Err(_) => write_float(out, n.parse().unwrap_or(f64::NAN)),
+        },
+        Val::Str(s) => serde_json::to_writer(&mut *out, s.as_str())?,
+        Val::Arr(a) => {
+            out.push(b'[');
+            for (i, v) in a.iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                write(out, v, precise)?;
+            }
+            out.push(b']');
+        }
+        Val::Obj(o) if precise => write_fields(out, o.iter(), precise)?,
+        Val::Obj(o) => {
+            let mut fields: Vec<_> = o.iter().collect();
+            fields.sort_unstable_by_key(|(k, _)| *k);
+            write_fields(out, fields.into_iter(), precise)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether `num` is the text of a JSON number, as the lexer that reads the input checks it.
+fn is_number(num: &str) -> bool {
+    SliceLexer::new(num.as_bytes())
+        .exactly_one(|token, lexer| match token {
+            Token::DigitOrMinus => lexer.num_string().map(|_| ()).map_err(Error::Num),
+            _ => Err(Error::Token(Expect::Value)),
+        })
+        .is_ok()
+}
+
+/// Write a double like `serde_json`, where values that aren't finite are `null`.
+fn write_float(out: &mut Vec<u8>, f: f64) {
+    if f.is_finite() {
+        out.extend_from_slice(ryu::Buffer::new().format_finite(f).as_bytes());
+    } else {
+        out.extend_from_slice(b"null");
+    }
+}
+
+/// The text of a number read by `serde_json`: integers are exact and other numbers are the
+/// shortest text of the nearest double. Doubles are formatted with `ryu` like older versions of
+/// `serde_json` did, as newer ones write exponents with a sign and outputs mustn't depend on which
+/// version is used.
+fn normalize(num: &serde_json::Number) -> String {
+    match num.as_f64() {
+        Some(f) if num.is_f64() => ryu::Buffer::new().format_finite(f).to_owned(),
+        _ => num.to_string(),
+    }
+}
+
+fn write_fields<'a>(
+    out: &mut Vec<u8>,
+    fields: impl Iterator<Item = (&'a Rc<String>, &'a Val)>,
+    precise: bool,
+) -> Result<()> {
+    out.push(b'{');
+    for (i, (k, v)) in fields.enumerate() {
+        if i > 0 {
+            out.push(b',');
+        }
+        serde_json::to_writer(&mut *out, k.as_str())?;
+        out.push(b':');
+        write(out, v, precise)?;
+    }
+    out.push(b'}');
+    Ok(())
+}
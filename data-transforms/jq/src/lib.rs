@@ -0,0 +1,535 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A transform of JSON payloads using [jaq](https://github.com/01mf02/jaq), a clone of jq.
+//!
+//! The transform is independent of the Redpanda runtime: [`Transform::process`] takes a record and
+//! returns the records to write, so the same filter behaves identically when deployed as a Wasm
+//! transform, run by the native command-line tool or embedded in tests and other programs.
+
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Context, Result};
+use jaq_interpret::{Ctx, Filter, FilterT, RcIter, Val};
+use redpanda_transform_sdk::{
+    BorrowedHeader, BorrowedRecord, Record, RecordHeader, RecordWriter, WriteEvent, WriteOptions,
+    WrittenRecord,
+};
+use redpanda_transform_sdk_sr::SchemaRegistryClient;
+use regex::Regex;
+
+use codec::Codec;
+use config::{Clock, Config, ErrorPolicy, Limits, Tombstones};
+use error::{ErrorLog, ResultExt};
+
+pub use error::{Stage, TransformError};
+
+mod avro;
+mod binary;
+mod builtins;
+mod bytes;
+mod codec;
+mod compile;
+pub mod config;
+mod datetime;
+mod diagnostic;
+mod embedded;
+mod envelope;
+mod error;
+pub mod headers;
+mod json;
+mod metadata;
+mod prelude;
+mod protobuf;
+pub mod record;
+mod redact;
+pub mod registry;
+mod validation;
+
+// Outputs that are objects can set the headers of the record they are written as using this field,
+// which is removed from the written value.
+const HEADERS_FIELD: &str = "$__headers";
+// Likewise, outputs can set the topic they are written to using this field.
+const TOPIC_FIELD: &str = "$__topic";
+
+// Headers added to records written to the dead-letter topic, describing why they failed.
+const ERROR_STAGE_HEADER: &str = "jq.error.stage";
+const ERROR_MESSAGE_HEADER: &str = "jq.error.message";
+const ERROR_FILTER_HEADER: &str = "jq.error.filter";
+
+/// The most bytes of [`ERROR_MESSAGE_HEADER`], as errors can include the whole value they're about,
+/// which may be large or hold data that the filter was meant to redact.
+const MAX_ERROR_MESSAGE: usize = 1024;
+
+/// A compiled filter along with its configuration.
+pub struct Transform {
+    filter: Filter,
+    /// The filters of [`Config::routes`] and the topics they write to.
+    routes: Vec<(Filter, Rc<String>)>,
+    config: Config,
+    codec: Codec,
+    /// The values of the variables following [`compile::RECORD_VARS`], which are the same for
+    /// every record.
+    globals: Vec<Val>,
+    /// The compiled [`Config::redact_patterns`].
+    redact_patterns: Rc<[Regex]>,
+    /// The keys and values of the records being written by [`Transform::apply`], which is reused
+    /// for every record so that encoding them doesn't allocate once it's large enough.
+    buf: RefCell<Vec<u8>>,
+    /// The records that were skipped or passed through by the error policy.
+    errors: ErrorLog,
+}
+
+/// A record written by the transform.
+#[derive(Debug)]
+pub struct Output {
+    /// The topic to write the record to, or `None` for the transform's output topic.
+    pub topic: Option<String>,
+    pub record: Record,
+}
+
+impl Transform {
+    /// Compile the configured filter and the filters of its routes, failing with a report of each
+    /// error in them if they're invalid.
+    ///
+    /// Formats that use the schema registry use the cluster's registry, which is only available to
+    /// deployed transforms. Use [`Transform::with_registry`] to run them elsewhere.
+    pub fn new(config: Config) -> Result<Self> {
+        let registry =
+            (cfg!(target_os = "wasi") && config.uses_registry()).then(SchemaRegistryClient::new);
+        Self::build(config, registry)
+    }
+
+    /// Compile the configured filter like [`Transform::new`], looking up schemas with `registry`,
+    /// such as a [`registry::LocalRegistry`] wrapped with [`SchemaRegistryClient::new_wrapping`].
+    pub fn with_registry(config: Config, registry: SchemaRegistryClient) -> Result<Self> {
+        Self::build(config, Some(registry))
+    }
+
+    fn build(config: Config, registry: Option<SchemaRegistryClient>) -> Result<Self> {
+        let names = compile::var_names(config.args.iter().map(|(name, _)| name.as_str()))?;
+        let precise = config.preserve_precision;
+        let steps = config.limits.max_steps.is_some();
+        let filter = compile::compile(
+            &names,
+            &config.modules,
+            (&config.filter_name, &config.filter),
+            precise,
+            steps,
+        )?;
+        let routes = config
+            .routes
+            .iter()
+            .map(|route| {
+                let filter = compile::compile(
+                    &names,
+                    &config.modules,
+                    (&route.name, &route.filter),
+                    precise,
+                    steps,
+                )?;
+                Ok((filter, Rc::new(route.topic.clone())))
+            })
+            .collect::<Result<_>>()?;
+        let env = config
+            .env
+            .iter()
+            .map(|(k, v)| (Rc::new(k.clone()), Val::str(v.clone())))
+            .collect();
+        let mut globals = vec![Val::obj(env)];
+        globals.extend(config.args.iter().map(|(_, v)| Val::from(v.clone())));
+        let codec = Codec::new(&config, registry)?;
+        let redact_patterns = redact::compile_patterns(&config.redact_patterns)?;
+        Ok(Self {
+            filter,
+            routes,
+            config,
+            codec,
+            globals,
+            redact_patterns,
+            buf: RefCell::default(),
+            errors: ErrorLog::default(),
+        })
+    }
+
+    /// Run the transform on a record, returning the records to write.
+    ///
+    /// Records that cannot be processed are handled by the configured error policy, so this only
+    /// returns an error for the `fail` policy, which is a [`TransformError`].
+    pub fn process(&self, record: &WrittenRecord) -> Result<Vec<Output>> {
+        let mut buf = Vec::new();
+        match transform_record(self, record, &mut buf) {
+            Ok(encoded) => Ok(encoded
+                .iter()
+                .map(|e| Output {
+                    topic: e.topic.as_ref().map(|t| t.to_string()),
+                    record: e.to_record(&buf, record),
+                })
+                .collect()),
+            Err(err) => handle_error(self, record, err),
+        }
+    }
+
+    /// Process the record of a write event and write the outputs with `writer`, which is the
+    /// callback the Wasm transform registers with `on_record_written`.
+    ///
+    /// Unlike [`Transform::process`], outputs are written straight from a buffer that is reused
+    /// for every record instead of being copied into records of their own. Outputs that cannot be
+    /// written are handled by the error policy too, but those written before them stay written.
+    pub fn apply(&self, event: WriteEvent, writer: &mut RecordWriter) -> Result<()> {
+        let record = &event.record;
+        let mut buf = self.buf.borrow_mut();
+        buf.clear();
+        let err = match transform_record(self, record, &mut buf) {
+            Ok(encoded) => {
+                let written = encoded.iter().try_for_each(|e| match &e.topic {
+                    Some(topic) => writer
+                        .write_with_options(e.borrow(&buf, record), WriteOptions::to_topic(topic))
+                        .with_context(|| format!("cannot write to topic {topic:?}")),
+                    None => writer
+                        .write(e.borrow(&buf, record))
+                        .context("cannot write to the output topic"),
+                });
+                match written.stage(Stage::Write) {
+                    Ok(()) => return Ok(()),
+                    Err(err) => err,
+                }
+            }
+            Err(err) => err,
+        };
+        for output in handle_error(self, record, err)? {
+            let written = match &output.topic {
+                Some(topic) => writer
+                    .write_with_options(&output.record, WriteOptions::to_topic(topic))
+                    .with_context(|| format!("cannot write to topic {topic:?}")),
+                None => writer
+                    .write(&output.record)
+                    .context("cannot write to the output topic"),
+            };
+            // There is nowhere left to write a record that the error policy cannot write, such as
+            // to a dead-letter topic that isn't an output topic, so it's dropped rather than
+            // retried forever.
+            if let Err(err) = written.stage(Stage::Write) {
+                self.errors.log("dropped", &err);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A record to write, whose key and value were encoded into a buffer.
+struct Encoded {
+    /// The topic to write the record to, or `None` for the transform's output topic.
+    topic: Option<Rc<String>>,
+    key: Option<Range<usize>>,
+    value: Option<Range<usize>>,
+    /// The headers to write, or `None` for the headers of the input record.
+    headers: Option<Vec<RecordHeader>>,
+}
+
+impl Encoded {
+    fn borrow<'a>(&'a self, buf: &'a [u8], record: &'a WrittenRecord) -> BorrowedRecord<'a> {
+        let headers = match &self.headers {
+            Some(headers) => headers.iter().map(BorrowedHeader::from).collect(),
+            None => record.headers().to_vec(),
+        };
+        BorrowedRecord::new_with_headers(
+            self.key.clone().map(|r| &buf[r]),
+            self.value.clone().map(|r| &buf[r]),
+            headers,
+        )
+    }
+
+    fn to_record(&self, buf: &[u8], record: &WrittenRecord) -> Record {
+        let headers = match &self.headers {
+            Some(headers) => headers.clone(),
+            None => record.headers().iter().map(|h| h.to_owned()).collect(),
+        };
+        Record::new_with_headers(
+            self.key.clone().map(|r| buf[r].to_vec()),
+            self.value.clone().map(|r| buf[r].to_vec()),
+            headers,
+        )
+    }
+}
+
+/// Append the bytes written by `f` to `buf`, returning where they are.
+fn append(buf: &mut Vec<u8>, f: impl FnOnce(&mut Vec<u8>) -> Result<()>) -> Result<Range<usize>> {
+    let start = buf.len();
+    f(buf)?;
+    Ok(start..buf.len())
+}
+
+/// Append `bytes` to `buf`, returning where they are.
+fn copy(buf: &mut Vec<u8>, bytes: &[u8]) -> Range<usize> {
+    let start = buf.len();
+    buf.extend_from_slice(bytes);
+    start..buf.len()
+}
+
+/// Run the filter on a record, encoding the keys and values of its outputs into `buf`.
+///
+/// All outputs are encoded before anything is written, so a record that fails part way through is
+/// handled by the error policy as a whole.
+fn transform_record(
+    transform: &Transform,
+    record: &WrittenRecord,
+    buf: &mut Vec<u8>,
+) -> Result<Vec<Encoded>, TransformError> {
+    let config = &transform.config;
+    // Decode the value of the record.
+    let payloads = match (record.value(), config.tombstones) {
+        (Some(value), _) => transform.codec.decode(config, value).stage(Stage::Decode)?,
+        (None, Tombstones::Error) => {
+            return Err(anyhow!("record has no value")).stage(Stage::Decode);
+        }
+        (None, Tombstones::Drop) => return Ok(Vec::new()),
+        (None, Tombstones::Passthrough) => {
+            let key = record.key().map(|k| copy(buf, k));
+            return Ok(vec![Encoded {
+                topic: None,
+                key,
+                value: None,
+                headers: None,
+            }]);
+        }
+        (None, Tombstones::Filter) => vec![Val::Null],
+    };
+    if record.value().is_some() {
+        for payload in &payloads {
+            transform
+                .codec
+                .validate_input(payload)
+                .stage(Stage::Validate)?;
+        }
+    }
+    let inputs = RcIter::new(core::iter::empty());
+    datetime::set_now(match config.clock {
+        Clock::Record => record.timestamp(),
+        Clock::Wall => SystemTime::now(),
+    });
+    // Add the record's metadata as variables that can be referenced.
+    let vars = metadata::to_vals(record).chain(transform.globals.iter().cloned());
+    let ctx = Ctx::new(vars, &inputs);
+    let limits = &config.limits;
+    builtins::reset_steps(limits.max_steps);
+    redact::set_patterns(transform.redact_patterns.clone());
+    let start = buf.len();
+    // Run the filter and then each route on each input, and encode each output as a record for
+    // the topic of the filter.
+    let mut records = Vec::new();
+    for payload in payloads {
+        let input = if config.envelope {
+            envelope::to_val(record, payload)
+        } else {
+            payload
+        };
+        let mut run = |route: Option<usize>| {
+            let (filter, topic) = match route {
+                Some(i) => (&transform.routes[i].0, Some(&transform.routes[i].1)),
+                None => (&transform.filter, None),
+            };
+            for output in filter.run((ctx.clone(), input.clone())) {
+                check_steps(limits)?;
+                let output = output.map_err(|e| anyhow!("{e}")).stage(Stage::Filter)?;
+                if limits.max_outputs == Some(records.len()) {
+                    return Err(anyhow!(
+                        "filter produced more than {} outputs, see MAX_OUTPUTS",
+                        records.len()
+                    ))
+                    .stage(Stage::Limit);
+                }
+                let encoded = encode_record(transform, record, output, topic, buf)?;
+                if let Some(max) = limits
+                    .max_output_bytes
+                    .filter(|max| buf.len() - start > *max)
+                {
+                    return Err(anyhow!(
+                        "outputs are larger than {max} bytes, see MAX_OUTPUT_BYTES"
+                    ))
+                    .stage(Stage::Limit);
+                }
+                records.push(encoded);
+            }
+            Ok(())
+        };
+        run(None)?;
+        for i in 0..transform.routes.len() {
+            run(Some(i)).map_err(|mut err| {
+                let name = &config.routes[i].name;
+                err.error = err.error.context(format!("in {name}"));
+                err.route = Some(i);
+                err
+            })?;
+        }
+    }
+    check_steps(limits)?;
+    Ok(records)
+}
+
+fn check_steps(limits: &Limits) -> Result<(), TransformError> {
+    match limits.max_steps {
+        Some(max) if builtins::steps_exhausted() => {
+            Err(anyhow!("filter took more than {max} steps, see MAX_STEPS")).stage(Stage::Limit)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Encode an output as a record for `topic`, or the transform's output topic for `None`, unless
+/// the output sets a topic of its own. The value of the record is validated before it's encoded.
+fn encode_record(
+    transform: &Transform,
+    record: &WrittenRecord,
+    output: Val,
+    topic: Option<&Rc<String>>,
+    buf: &mut Vec<u8>,
+) -> Result<Encoded, TransformError> {
+    let config = &transform.config;
+    let codec = &transform.codec;
+    let encode_value = |buf: &mut Vec<u8>, value: &Val| {
+        codec.validate_output(value).stage(Stage::Validate)?;
+        append(buf, |buf| codec.encode(config, value, buf)).stage(Stage::Encode)
+    };
+    if config.envelope {
+        let envelope = envelope::from_val(output, record).stage(Stage::Encode)?;
+        let key = envelope
+            .key
+            .map(|k| append(buf, |buf| envelope::encode_key(config, &k, buf)))
+            .transpose()
+            .stage(Stage::Encode)?;
+        let value = envelope.value.map(|v| encode_value(buf, &v)).transpose()?;
+        return Ok(Encoded {
+            topic: envelope.topic.or_else(|| topic.cloned()),
+            key,
+            value,
+            headers: Some(envelope.headers),
+        });
+    }
+    let (output, headers) = take_field(output, HEADERS_FIELD);
+    let headers = headers
+        .map(|h| headers::from_val(&h).context("invalid output headers"))
+        .transpose()
+        .stage(Stage::Encode)?;
+    let (output, output_topic) = take_field(output, TOPIC_FIELD);
+    let topic = match output_topic {
+        None | Some(Val::Null) => topic.cloned(),
+        Some(Val::Str(topic)) => Some(topic),
+        Some(other) => {
+            return Err(anyhow!("{TOPIC_FIELD} must be a string, got {other}")).stage(Stage::Encode)
+        }
+    };
+    let headers = match headers {
+        Some(headers) => Some(headers),
+        None if config.preserve_headers => None,
+        None => Some(Vec::new()),
+    };
+    let key = record.key().map(|k| copy(buf, k));
+    // A tombstone the filter outputs as `null` stays a tombstone, so that deletes aren't turned
+    // into records with a `null` value that compaction would keep.
+    let value = match output {
+        Val::Null if record.value().is_none() => None,
+        output => Some(encode_value(buf, &output)?),
+    };
+    Ok(Encoded {
+        topic,
+        key,
+        value,
+        headers,
+    })
+}
+
+fn handle_error(
+    transform: &Transform,
+    record: &WrittenRecord,
+    err: TransformError,
+) -> Result<Vec<Output>> {
+    let config = &transform.config;
+    let copy = |headers| {
+        Record::new_with_headers(
+            record.key().map(<[u8]>::to_vec),
+            record.value().map(<[u8]>::to_vec),
+            headers,
+        )
+    };
+    let mut headers: Vec<_> = record.headers().iter().map(|h| h.to_owned()).collect();
+    let filter = match err.route {
+        Some(i) => &config.routes[i].filter,
+        None => &config.filter,
+    };
+    let policy = match err.stage {
+        Stage::Validate => &config.validation_policy,
+        _ => &config.error_policy,
+    };
+    Ok(match policy {
+        ErrorPolicy::Fail => return Err(err.into()),
+        ErrorPolicy::Skip => {
+            transform.errors.log("skipped", &err);
+            Vec::new()
+        }
+        ErrorPolicy::Passthrough => {
+            transform.errors.log("passed through", &err);
+            vec![Output {
+                topic: None,
+                record: copy(headers),
+            }]
+        }
+        ErrorPolicy::DeadLetter(topic) => {
+            let error_headers = [
+                (ERROR_STAGE_HEADER, err.stage.to_string()),
+                (ERROR_MESSAGE_HEADER, truncate(format!("{:#}", err.error))),
+                (ERROR_FILTER_HEADER, filter.to_owned()),
+            ];
+            headers.extend(
+                error_headers
+                    .into_iter()
+                    .map(|(k, v)| RecordHeader::new(k.as_bytes().to_vec(), Some(v.into_bytes()))),
+            );
+            vec![Output {
+                topic: Some(topic.clone()),
+                record: copy(headers),
+            }]
+        }
+    })
+}
+
+/// Cut `message` down to [`MAX_ERROR_MESSAGE`] bytes, ending with an ellipsis if it was cut.
+fn truncate(mut message: String) -> String {
+    const ELLIPSIS: &str = "…";
+    if message.len() > MAX_ERROR_MESSAGE {
+        let mut end = MAX_ERROR_MESSAGE - ELLIPSIS.len();
+        while !message.is_char_boundary(end) {
+            end -= 1;
+        }
+        message.truncate(end);
+        message.push_str(ELLIPSIS);
+    }
+    message
+}
+
+/// Split a field such as [`HEADERS_FIELD`] off an output, if it's an object with the field.
+fn take_field(output: Val, name: &str) -> (Val, Option<Val>) {
+    let Val::Obj(mut fields) = output else {
+        return (output, None);
+    };
+    let field = name.to_owned();
+    if !fields.contains_key(&field) {
+        return (Val::Obj(fields), None);
+    }
+    let value = Rc::make_mut(&mut fields).shift_remove(&field);
+    (Val::Obj(fields), value)
+}
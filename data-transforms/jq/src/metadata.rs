@@ -0,0 +1,65 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Metadata of the record being transformed, which is available to filters as variables.
+
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use jaq_interpret::Val;
+use jiff::Timestamp;
+use redpanda_transform_sdk::WrittenRecord;
+
+use crate::{datetime, headers};
+
+/// The values of the variables named by [`crate::compile::RECORD_VARS`] for a record, where `$NOW`
+/// is the time set by [`datetime::set_now`].
+pub fn to_vals(record: &WrittenRecord) -> impl Iterator<Item = Val> {
+    let key = record
+        .key()
+        .map(|k| Val::str(String::from_utf8_lossy(k).to_string()))
+        .unwrap_or(Val::Null);
+    [
+        key,
+        headers::to_val(record.headers()),
+        Val::Bool(record.value().is_none()),
+        timestamp_millis(record.timestamp()),
+        timestamp_iso(record.timestamp()),
+        size(record.key()),
+        size(record.value()),
+        datetime::now(),
+    ]
+    .into_iter()
+}
+
+/// Record timestamps have millisecond precision, which doesn't fit in the 32 bit integers jaq uses
+/// on Wasm so fall back to its arbitrary precision numbers in that case.
+pub fn timestamp_millis(ts: SystemTime) -> Val {
+    let millis = match ts.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    };
+    isize::try_from(millis).map_or_else(|_| Val::Num(Rc::new(millis.to_string())), Val::Int)
+}
+
+/// Timestamps are formatted with exactly millisecond precision, such as `2024-02-12T14:58:41.390Z`.
+/// Times outside of the years -9999 to 9999 are `null`.
+fn timestamp_iso(ts: SystemTime) -> Val {
+    Timestamp::try_from(ts).map_or(Val::Null, |ts| Val::str(format!("{ts:.3}")))
+}
+
+/// Missing keys and values are `null`, so that they can be told apart from empty ones.
+fn size(bytes: Option<&[u8]>) -> Val {
+    bytes.map_or(Val::Null, |b| Val::Int(b.len() as isize))
+}
@@ -0,0 +1,94 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! What filters are compiled against: the names of the natives of [`crate::builtins`] and the
+//! definitions of jq that go with them.
+//!
+//! The build script includes this module to validate embedded filters, so it only depends on jaq.
+//! The natives themselves, and everything they depend on, are left to the transform.
+
+use jaq_interpret::Native;
+
+/// Definitions that the jq standard library is compiled against in place of natives of jaq when
+/// steps are counted.
+///
+/// Each output of `range` takes a step, as it can loop without calling any definitions.
+pub const NATIVE_DEFS: &str = "def range(from; upto; by): __range(from; upto; by) | __step;";
+
+/// Definitions that replace those of the jq standard library in precise mode.
+///
+/// jaq deletes fields by moving the last field of the object into their place, so `del` puts the
+/// remaining fields back in the order they had. Otherwise fields are sorted when they're written,
+/// so `del` is left as it is rather than paying for the order to be restored.
+pub const PRECISE_DEFS: &str =
+    "def del(f): . as $__orig | (f |= empty) | __restore_order($__orig);";
+
+/// The name of the native that takes a step, which is called by every definition.
+pub const STEP: &str = "__step";
+
+/// The names and arities of the natives of [`crate::builtins`]. Those that jaq also has replace
+/// jaq's.
+pub const NATIVES: &[(&str, usize)] = &[
+    ("__restore_order", 1),
+    (STEP, 0),
+    // Bytes.
+    ("sha256", 0),
+    ("sha1", 0),
+    ("md5", 0),
+    ("hmac_sha256", 1),
+    ("xxhash64", 0),
+    ("murmur2", 0),
+    ("murmur2_partition", 1),
+    ("uuid_v5", 1),
+    ("to_hex", 0),
+    ("to_base64", 0),
+    ("from_base64", 0),
+    ("@hex", 0),
+    ("@base64d", 0),
+    // Dates and times.
+    ("now", 0),
+    ("from_rfc3339", 0),
+    ("to_rfc3339", 0),
+    ("to_rfc3339", 1),
+    ("parse_time", 1),
+    ("parse_time", 2),
+    ("format_time", 1),
+    ("format_time", 2),
+    ("from_epoch", 1),
+    ("to_epoch", 1),
+    ("truncate_time", 1),
+    ("truncate_time", 2),
+    // Redaction.
+    ("redact_email", 0),
+    ("redact_phone", 0),
+    ("redact_credit_card", 0),
+    ("redact_ip", 0),
+    ("redact_patterns", 0),
+    ("redact_pii", 0),
+];
+
+/// The natives `ours`, which are those of [`NATIVES`], followed by those of jaq without those that
+/// `ours` replace. `range` is also named `__range`, so that [`NATIVE_DEFS`] can replace it.
+pub fn with_core(
+    ours: impl Iterator<Item = (String, usize, Native)>,
+) -> impl Iterator<Item = (String, usize, Native)> {
+    let core = jaq_core::core()
+        .filter(|(name, arity, _)| !NATIVES.contains(&(name.as_str(), *arity)))
+        .flat_map(|(name, arity, native)| {
+            let range = (name == "range" && arity == 3)
+                .then(|| ("__range".to_owned(), arity, native.clone()));
+            std::iter::once((name, arity, native)).chain(range)
+        });
+    ours.chain(core)
+}
@@ -0,0 +1,221 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Protobuf messages, which are read into JSON and written from it with the
+//! [proto3 JSON mapping](https://protobuf.dev/programming-guides/proto3/#json).
+//!
+//! Messages are either bare messages of a type from a descriptor set, or in the schema registry's
+//! wire format: a zero magic byte, the ID of the schema as a big-endian 32-bit integer, the
+//! indexes of the message type in the schema and then the message itself. The indexes are a
+//! zigzag varint count followed by that many zigzag varints, the first being the index of a
+//! message in the file and each next one of a message nested in the previous, except that the
+//! first message of the file is written as a single `0`.
+
+use anyhow::{bail, Context, Result};
+use jaq_interpret::Val;
+use prost::Message;
+use prost_reflect::{DynamicMessage, FileDescriptor, MessageDescriptor};
+use protox::file::{ChainFileResolver, File, FileResolver, GoogleFileResolver};
+use protox::Compiler;
+use redpanda_transform_sdk_sr::{decode_schema_id, SchemaId};
+
+use crate::json;
+use crate::registry::Registry;
+
+/// A compiled Protobuf schema from the registry.
+pub struct ProtobufSchema {
+    id: SchemaId,
+    file: FileDescriptor,
+}
+
+/// A message type that outputs are written as.
+pub struct OutputMessage {
+    desc: MessageDescriptor,
+    /// The header of the wire format, or empty for bare messages.
+    header: Vec<u8>,
+}
+
+/// Read a message in the wire format, looking up its schema in the registry.
+pub fn decode(registry: &Registry, value: &[u8], precise: bool) -> Result<Val> {
+    let (id, mut message) = decode_schema_id(value)?;
+    let indexes = read_indexes(&mut message)?;
+    let desc = registry.protobuf(id)?.message(&indexes)?;
+    read(desc, message, precise)
+}
+
+/// Read a bare message of type `desc` into JSON.
+pub fn read(desc: MessageDescriptor, message: &[u8], precise: bool) -> Result<Val> {
+    let message = DynamicMessage::decode(desc, message)?;
+    json::parse(&serde_json::to_vec(&message)?, precise)
+}
+
+impl ProtobufSchema {
+    /// Compile the definition of the schema registered with `id`, where `references` are the
+    /// names it imports the schemas it references as and their definitions. The well-known types
+    /// such as `google/protobuf/timestamp.proto` can be imported without being referenced.
+    pub fn parse(id: SchemaId, definition: &str, references: &[(String, String)]) -> Result<Self> {
+        let name = format!("schema-{}.proto", id.0);
+        let mut sources = vec![(name.clone(), definition.to_owned())];
+        sources.extend_from_slice(references);
+        let mut resolver = ChainFileResolver::new();
+        resolver.add(Sources(sources));
+        resolver.add(GoogleFileResolver::new());
+        let mut compiler = Compiler::with_file_resolver(resolver);
+        compiler.include_imports(true).open_file(&name)?;
+        let file = compiler
+            .descriptor_pool()
+            .get_file_by_name(&name)
+            .context("schema was not compiled")?;
+        Ok(Self { id, file })
+    }
+
+    /// The message type at `indexes` in the schema.
+    fn message(&self, indexes: &[usize]) -> Result<MessageDescriptor> {
+        let not_found = || format!("schema {} has no message at indexes {indexes:?}", self.id.0);
+        let (first, nested) = indexes.split_first().with_context(not_found)?;
+        let mut desc = self.file.messages().nth(*first).with_context(not_found)?;
+        for i in nested {
+            let child = desc.child_messages().nth(*i).with_context(not_found)?;
+            desc = child;
+        }
+        Ok(desc)
+    }
+
+    /// The message type named `name` to write outputs as, or the first in the schema for `None`.
+    pub fn output(&self, name: Option<&str>) -> Result<OutputMessage> {
+        let desc = match name {
+            Some(name) => {
+                let desc = self
+                    .file
+                    .parent_pool()
+                    .get_message_by_name(name)
+                    .with_context(|| format!("schema {} has no message {name}", self.id.0))?;
+                if desc.parent_file() != self.file {
+                    bail!(
+                        "message {name} is imported by schema {}, not defined in it",
+                        self.id.0
+                    );
+                }
+                desc
+            }
+            None => self
+                .file
+                .messages()
+                .next()
+                .with_context(|| format!("schema {} has no messages", self.id.0))?,
+        };
+        let mut header = vec![0];
+        header.extend_from_slice(&self.id.0.to_be_bytes());
+        write_indexes(&mut header, &indexes(&desc));
+        Ok(OutputMessage { desc, header })
+    }
+}
+
+impl OutputMessage {
+    /// Write outputs as bare messages of type `desc`.
+    pub fn bare(desc: MessageDescriptor) -> Self {
+        Self {
+            desc,
+            header: Vec::new(),
+        }
+    }
+
+    /// Write `val` as a message, appending it to `out`.
+    pub fn write(&self, val: &Val, out: &mut Vec<u8>) -> Result<()> {
+        // Write the value as JSON with its numbers exactly as they are, as 64-bit integers can be
+        // larger than doubles can hold.
+        let mut json = Vec::new();
+        json::write(&mut json, val, true)?;
+        let mut deserializer = serde_json::Deserializer::from_slice(&json);
+        let message = DynamicMessage::deserialize(self.desc.clone(), &mut deserializer)?;
+        out.extend_from_slice(&self.header);
+        message.encode(out)?;
+        Ok(())
+    }
+}
+
+/// Protobuf sources by file name, which schemas are compiled from.
+struct Sources(Vec<(String, String)>);
+
+impl FileResolver for Sources {
+    fn open_file(&self, name: &str) -> Result<File, protox::Error> {
+        match self.0.iter().find(|(n, _)| n == name) {
+            Some((name, source)) => File::from_source(name, source),
+            None => Err(protox::Error::file_not_found(name)),
+        }
+    }
+}
+
+/// The indexes of a message type from the top of its file.
+fn indexes(desc: &MessageDescriptor) -> Vec<usize> {
+    let (mut indexes, position) = match desc.parent_message() {
+        Some(parent) => {
+            let position = parent.child_messages().position(|m| m == *desc);
+            (indexes(&parent), position)
+        }
+        None => (
+            Vec::new(),
+            desc.parent_file().messages().position(|m| m == *desc),
+        ),
+    };
+    // Every message is one of the messages of its parent.
+    indexes.push(position.unwrap_or_default());
+    indexes
+}
+
+fn read_indexes(buf: &mut &[u8]) -> Result<Vec<usize>> {
+    let count = read_varint(buf)?;
+    if count == 0 {
+        return Ok(vec![0]);
+    }
+    (0..count)
+        .map(|_| read_varint(buf))
+        .collect::<Result<_>>()
+        .context("invalid message indexes")
+}
+
+fn write_indexes(out: &mut Vec<u8>, indexes: &[usize]) {
+    if indexes == [0] {
+        write_varint(out, 0);
+        return;
+    }
+    write_varint(out, indexes.len());
+    for i in indexes {
+        write_varint(out, *i);
+    }
+}
+
+/// Read a zigzag varint, which must be a non-negative index or count.
+fn read_varint(buf: &mut &[u8]) -> Result<usize> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = buf.split_first().context("truncated message indexes")?;
+        *buf = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            let value = (value >> 1) as i64 ^ -((value & 1) as i64);
+            return usize::try_from(value).context("negative message index");
+        }
+    }
+    bail!("message index is too long")
+}
+
+fn write_varint(out: &mut Vec<u8>, value: usize) {
+    let mut value = (value as u64) << 1;
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
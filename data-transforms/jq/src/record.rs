@@ -0,0 +1,120 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A JSON representation of records, for reading and printing them outside of Redpanda such as in
+//! the command-line tool and tests:
+//!
+//! ```json
+//! {"key": "user-1", "value": "{\"foo\":42}", "headers": {"tenant": "acme"}, "timestamp": 1707749921393}
+//! ```
+//!
+//! Keys, values and header values are strings, `{"base64": "..."}` objects for binary data or
+//! `null`, the same as headers are represented to filters (see [`crate::headers`]). The timestamp is
+//! in milliseconds since the Unix epoch. Records written to a topic other than the transform's
+//! output topic also have a `topic` field.
+
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use jaq_interpret::Val;
+use redpanda_transform_sdk::{BorrowedHeader, RecordHeader, WrittenRecord};
+
+use crate::{headers, Output};
+
+/// An owned record to run the transform on.
+#[derive(Debug, Clone)]
+pub struct InputRecord {
+    pub key: Option<Vec<u8>>,
+    pub value: Option<Vec<u8>>,
+    pub headers: Vec<RecordHeader>,
+    pub timestamp: SystemTime,
+}
+
+impl InputRecord {
+    /// A record with only a value, timestamped with the current time.
+    pub fn from_value(value: Vec<u8>) -> Self {
+        Self {
+            key: None,
+            value: Some(value),
+            headers: Vec::new(),
+            timestamp: SystemTime::now(),
+        }
+    }
+
+    /// Parse a record from its JSON representation, where every field is optional. Records without
+    /// a timestamp are given the current time.
+    pub fn from_json(json: serde_json::Value) -> Result<Self> {
+        let serde_json::Value::Object(fields) = json else {
+            bail!("record must be an object, got {json}");
+        };
+        let mut record = Self {
+            key: None,
+            value: None,
+            headers: Vec::new(),
+            timestamp: SystemTime::now(),
+        };
+        for (name, field) in fields {
+            match name.as_str() {
+                "key" => record.key = bytes_from_json(field).context("invalid key")?,
+                "value" => record.value = bytes_from_json(field).context("invalid value")?,
+                "headers" => record.headers = headers::from_val(&Val::from(field))?,
+                "timestamp" => record.timestamp = timestamp_from_millis(&field.to_string())?,
+                _ => bail!("unexpected field {name:?} in record"),
+            }
+        }
+        Ok(record)
+    }
+
+    /// Borrow the record as it's passed to transforms by Redpanda.
+    pub fn as_written(&self) -> WrittenRecord<'_> {
+        WrittenRecord::new_with_headers(
+            self.key.as_deref(),
+            self.value.as_deref(),
+            self.timestamp,
+            self.headers.iter().map(BorrowedHeader::from).collect(),
+        )
+    }
+}
+
+/// The JSON representation of a record written by the transform.
+pub fn output_to_json(output: &Output) -> serde_json::Value {
+    let record = &output.record;
+    let bytes = |b: Option<&[u8]>| b.map_or(Val::Null, headers::bytes_to_val);
+    let headers: Vec<BorrowedHeader> = record.headers().collect();
+    let mut fields = vec![
+        ("key", bytes(record.key())),
+        ("value", bytes(record.value())),
+        ("headers", headers::to_val(&headers)),
+    ];
+    if let Some(topic) = &output.topic {
+        fields.insert(0, ("topic", Val::str(topic.clone())));
+    }
+    let fields = fields
+        .into_iter()
+        .map(|(k, v)| (Rc::new(k.to_owned()), v))
+        .collect();
+    serde_json::Value::from(Val::obj(fields))
+}
+
+pub fn timestamp_from_millis(millis: &str) -> Result<SystemTime> {
+    let millis: u64 = millis.parse().with_context(|| {
+        format!("timestamp must be milliseconds since the Unix epoch, got {millis}")
+    })?;
+    Ok(UNIX_EPOCH + Duration::from_millis(millis))
+}
+
+fn bytes_from_json(json: serde_json::Value) -> Result<Option<Vec<u8>>> {
+    headers::bytes_from_val(&Val::from(json))
+}
@@ -0,0 +1,250 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Functions of filters that redact personal data, like the redaction lab.
+//!
+//! Each function finds its kind of data anywhere in a string, so it works the same on a field that
+//! holds only an email address as on free text that mentions one. Arrays and objects have the
+//! strings in them redacted, and other values are left as they are.
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use jaq_interpret::results::box_once;
+use jaq_interpret::{RunPtr, Val};
+use regex::{Captures, Regex};
+
+/// What matches of the configured patterns are replaced with.
+const REDACTED: &str = "REDACTED";
+
+/// Replaces a kind of data in a string, returning the string as is if there is none.
+type Redactor = fn(&str) -> Cow<'_, str>;
+
+pub const NATIVES: &[(&str, usize, RunPtr)] = &[
+    ("redact_email", 0, |_, cv| {
+        box_once(Ok(redact(&cv.1, &[email])))
+    }),
+    ("redact_phone", 0, |_, cv| {
+        box_once(Ok(redact(&cv.1, &[phone])))
+    }),
+    ("redact_credit_card", 0, |_, cv| {
+        box_once(Ok(redact(&cv.1, &[credit_card])))
+    }),
+    ("redact_ip", 0, |_, cv| {
+        box_once(Ok(redact(&cv.1, &[ipv6, ipv4])))
+    }),
+    ("redact_patterns", 0, |_, cv| {
+        box_once(Ok(redact(&cv.1, &[patterns])))
+    }),
+    // Patterns go first as they're the most specific, and phone numbers last as their digits could
+    // be part of any of the others.
+    ("redact_pii", 0, |_, cv| {
+        box_once(Ok(redact(
+            &cv.1,
+            &[patterns, email, credit_card, ipv6, ipv4, phone],
+        )))
+    }),
+];
+
+thread_local! {
+    /// The patterns of `redact_patterns`, which are those of the transform that is running.
+    static PATTERNS: RefCell<Rc<[Regex]>> = RefCell::new(Rc::new([]));
+}
+
+/// Compile the patterns of [`crate::config::Config::redact_patterns`].
+pub fn compile_patterns(patterns: &[(String, String)]) -> Result<Rc<[Regex]>> {
+    patterns
+        .iter()
+        .map(|(name, pattern)| {
+            Regex::new(pattern).with_context(|| {
+                format!("environment variable {name} is not a valid regular expression")
+            })
+        })
+        .collect()
+}
+
+/// Make `patterns` the patterns of `redact_patterns` for the filter that runs next.
+pub fn set_patterns(patterns: Rc<[Regex]>) {
+    PATTERNS.set(patterns);
+}
+
+fn redact(val: &Val, redactors: &[Redactor]) -> Val {
+    match val {
+        Val::Str(s) => {
+            let mut redacted = Cow::Borrowed(s.as_str());
+            for redactor in redactors {
+                if let Cow::Owned(r) = redactor(&redacted) {
+                    redacted = Cow::Owned(r);
+                }
+            }
+            match redacted {
+                Cow::Borrowed(_) => val.clone(),
+                Cow::Owned(r) => Val::str(r),
+            }
+        }
+        Val::Arr(a) => Val::arr(a.iter().map(|v| redact(v, redactors)).collect()),
+        Val::Obj(o) => Val::obj(
+            o.iter()
+                .map(|(k, v)| (k.clone(), redact(v, redactors)))
+                .collect(),
+        ),
+        _ => val.clone(),
+    }
+}
+
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).unwrap())
+}
+
+/// Replace each match of `re` in `s` with what `f` returns for it, or leave it as is for `None`.
+fn replace<'a>(
+    s: &'a str,
+    re: &Regex,
+    f: impl Fn(&str, Range<usize>) -> Option<String>,
+) -> Cow<'a, str> {
+    re.replace_all(s, |caps: &Captures| {
+        let m = caps.get(0).unwrap();
+        f(s, m.range()).unwrap_or_else(|| m.as_str().to_owned())
+    })
+}
+
+/// Whether the match at `range` isn't part of a longer word or number.
+fn isolated(s: &str, range: &Range<usize>) -> bool {
+    let word = |c: char| c.is_alphanumeric() || c == '_';
+    !s[..range.start].chars().next_back().is_some_and(word)
+        && !s[range.end..].chars().next().is_some_and(word)
+}
+
+/// Replace all but the last `keep` digits of `s` with `X`, keeping any separators.
+fn mask_digits(s: &str, keep: usize) -> String {
+    let mut left = s.chars().filter(char::is_ascii_digit).count();
+    s.chars()
+        .map(|c| {
+            if !c.is_ascii_digit() {
+                return c;
+            }
+            left -= 1;
+            if left < keep {
+                c
+            } else {
+                'X'
+            }
+        })
+        .collect()
+}
+
+/// Email addresses keep their domain, `ada@example.com` is `redacted@example.com`.
+fn email(s: &str) -> Cow<'_, str> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = regex(
+        &RE,
+        r"(?i)\b[a-z0-9._%+-]+@([a-z0-9-]+(?:\.[a-z0-9-]+)*\.[a-z]{2,})\b",
+    );
+    re.replace_all(s, "redacted@$1")
+}
+
+/// Credit card numbers of 13 to 19 digits with a valid Luhn check digit, optionally separated by
+/// spaces or hyphens, keep their last four digits.
+fn credit_card(s: &str) -> Cow<'_, str> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = regex(&RE, r"\b\d(?:[ -]?\d){12,18}\b");
+    replace(s, re, |s, range| {
+        let number = &s[range];
+        luhn(number).then(|| mask_digits(number, 4))
+    })
+}
+
+fn luhn(number: &str) -> bool {
+    let digits = number.bytes().filter(u8::is_ascii_digit).map(|b| b - b'0');
+    let sum: u32 = digits
+        .rev()
+        .enumerate()
+        .map(|(i, d)| match (i % 2, d * 2) {
+            (0, _) => u32::from(d),
+            (_, doubled) if doubled > 9 => u32::from(doubled - 9),
+            (_, doubled) => u32::from(doubled),
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// IPv4 addresses keep their first three octets, `192.0.2.33` is `192.0.2.0`.
+fn ipv4(s: &str) -> Cow<'_, str> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = regex(&RE, r"\b(?:\d{1,3}\.){3}\d{1,3}\b");
+    replace(s, re, |s, range| {
+        // Not part of a longer dotted number, such as a version.
+        let dotted = |rest: &str| {
+            rest.starts_with('.') && rest[1..].starts_with(|c: char| c.is_ascii_digit())
+        };
+        let before: String = s[..range.start].chars().rev().take(2).collect();
+        if dotted(&before) || dotted(&s[range.end..]) {
+            return None;
+        }
+        let [a, b, c, _] = s[range].parse::<Ipv4Addr>().ok()?.octets();
+        Some(Ipv4Addr::new(a, b, c, 0).to_string())
+    })
+}
+
+/// IPv6 addresses keep their first three groups, `2001:db8:85a3::8a2e:370:7334` is
+/// `2001:db8:85a3::`.
+fn ipv6(s: &str) -> Cow<'_, str> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = regex(&RE, r"[0-9A-Fa-f:.]*:[0-9A-Fa-f:.]*:[0-9A-Fa-f:.]*");
+    replace(s, re, |s, range| {
+        if !isolated(s, &range) {
+            return None;
+        }
+        // The end of a sentence isn't part of the address.
+        let candidate = &s[range];
+        let address = candidate.trim_end_matches('.');
+        let [a, b, c, ..] = address.parse::<Ipv6Addr>().ok()?.segments();
+        let masked = Ipv6Addr::new(a, b, c, 0, 0, 0, 0, 0);
+        Some(format!("{masked}{}", &candidate[address.len()..]))
+    })
+}
+
+/// Phone numbers of 9 to 15 digits, optionally starting with `+` and with their digits separated
+/// by spaces, dots, hyphens or parentheses, keep their last four digits.
+fn phone(s: &str) -> Cow<'_, str> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    static NOT_PHONE: OnceLock<Regex> = OnceLock::new();
+    let re = regex(&RE, r"\+?\(?\d(?:[ .()-]{0,2}\d){8,14}");
+    // Dates and IPv4 addresses have as many digits as phone numbers.
+    let not_phone = regex(
+        &NOT_PHONE,
+        r"^(?:\d{4}[-/.]\d{1,2}[-/.]\d{1,2}|\d{1,3}(?:\.\d{1,3}){3}$)",
+    );
+    replace(s, re, |s, range| {
+        let number = &s[range.clone()];
+        (isolated(s, &range) && !not_phone.is_match(number)).then(|| mask_digits(number, 4))
+    })
+}
+
+fn patterns(s: &str) -> Cow<'_, str> {
+    PATTERNS.with_borrow(|patterns| {
+        let mut redacted = Cow::Borrowed(s);
+        for re in patterns.iter() {
+            if let Cow::Owned(r) = re.replace_all(&redacted, REDACTED) {
+                redacted = Cow::Owned(r);
+            }
+        }
+        redacted
+    })
+}
@@ -0,0 +1,261 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Schemas from Redpanda's schema registry, which the Avro and Protobuf formats read and write
+//! record values with, and which values can be validated with as JSON Schemas.
+//!
+//! Deployed transforms look schemas up in the cluster's registry. Elsewhere, such as in tests and
+//! the command-line tool, schemas are registered in a [`LocalRegistry`] instead.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use anyhow::{bail, Context, Result};
+use redpanda_transform_sdk_sr::{
+    Schema, SchemaFormat, SchemaId, SchemaRegistryClient, SchemaRegistryClientImpl,
+    SchemaRegistryError, SchemaVersion, SubjectSchema,
+};
+
+use crate::avro::AvroSchema;
+use crate::protobuf::ProtobufSchema;
+use crate::validation::JsonSchema;
+
+/// A schema registry client that parses each schema once.
+pub(crate) struct Registry {
+    client: SchemaRegistryClient,
+    avro: RefCell<HashMap<SchemaId, Rc<AvroSchema>>>,
+    protobuf: RefCell<HashMap<SchemaId, Rc<ProtobufSchema>>>,
+}
+
+impl Registry {
+    pub fn new(client: SchemaRegistryClient) -> Self {
+        Self {
+            client,
+            avro: RefCell::default(),
+            protobuf: RefCell::default(),
+        }
+    }
+
+    /// The Avro schema with the given ID.
+    pub fn avro(&self, id: SchemaId) -> Result<Rc<AvroSchema>> {
+        if let Some(schema) = self.avro.borrow().get(&id) {
+            return Ok(schema.clone());
+        }
+        let schema = self
+            .client
+            .lookup_schema_by_id(id)
+            .with_context(|| format!("cannot look up schema {}", id.0))?;
+        let schema = Rc::new(self.parse_avro(id, &schema)?);
+        self.avro.borrow_mut().insert(id, schema.clone());
+        Ok(schema)
+    }
+
+    /// The latest Avro schema registered under `subject`.
+    pub fn latest_avro(&self, subject: &str) -> Result<Rc<AvroSchema>> {
+        let latest = self
+            .client
+            .lookup_latest_schema(subject)
+            .with_context(|| format!("cannot look up the latest schema of subject {subject:?}"))?;
+        let schema = Rc::new(self.parse_avro(latest.id(), latest.schema())?);
+        self.avro.borrow_mut().insert(latest.id(), schema.clone());
+        Ok(schema)
+    }
+
+    fn parse_avro(&self, id: SchemaId, schema: &Schema) -> Result<AvroSchema> {
+        if *schema.format() != SchemaFormat::Avro {
+            bail!("schema {} is {:?}, not Avro", id.0, schema.format());
+        }
+        let references: Vec<String> = self
+            .references(schema)?
+            .into_iter()
+            .map(|(_, definition)| definition)
+            .collect();
+        AvroSchema::parse(id, schema.schema(), &references)
+            .with_context(|| format!("schema {} is invalid", id.0))
+    }
+
+    /// The Protobuf schema with the given ID.
+    pub fn protobuf(&self, id: SchemaId) -> Result<Rc<ProtobufSchema>> {
+        if let Some(schema) = self.protobuf.borrow().get(&id) {
+            return Ok(schema.clone());
+        }
+        let schema = self
+            .client
+            .lookup_schema_by_id(id)
+            .with_context(|| format!("cannot look up schema {}", id.0))?;
+        let schema = Rc::new(self.parse_protobuf(id, &schema)?);
+        self.protobuf.borrow_mut().insert(id, schema.clone());
+        Ok(schema)
+    }
+
+    /// The latest Protobuf schema registered under `subject`.
+    pub fn latest_protobuf(&self, subject: &str) -> Result<Rc<ProtobufSchema>> {
+        let latest = self
+            .client
+            .lookup_latest_schema(subject)
+            .with_context(|| format!("cannot look up the latest schema of subject {subject:?}"))?;
+        let schema = Rc::new(self.parse_protobuf(latest.id(), latest.schema())?);
+        self.protobuf
+            .borrow_mut()
+            .insert(latest.id(), schema.clone());
+        Ok(schema)
+    }
+
+    fn parse_protobuf(&self, id: SchemaId, schema: &Schema) -> Result<ProtobufSchema> {
+        if *schema.format() != SchemaFormat::Protobuf {
+            bail!("schema {} is {:?}, not Protobuf", id.0, schema.format());
+        }
+        let references = self.references(schema)?;
+        ProtobufSchema::parse(id, schema.schema(), &references)
+            .with_context(|| format!("schema {} is invalid", id.0))
+    }
+
+    /// The latest JSON Schema registered under `subject`.
+    pub fn latest_json(&self, subject: &str) -> Result<JsonSchema> {
+        let latest = self
+            .client
+            .lookup_latest_schema(subject)
+            .with_context(|| format!("cannot look up the latest schema of subject {subject:?}"))?;
+        let (id, schema) = (latest.id(), latest.schema());
+        if *schema.format() != SchemaFormat::Json {
+            bail!("schema {} is {:?}, not JSON", id.0, schema.format());
+        }
+        let references = self.references(schema)?;
+        let name = format!("schema {} of subject {subject:?}", id.0);
+        JsonSchema::parse(&name, schema.schema(), &references)
+            .with_context(|| format!("schema {} is invalid", id.0))
+    }
+
+    /// The names and definitions of the schemas that `schema` references, and those that they
+    /// reference in turn, with each schema after those it references.
+    fn references(&self, schema: &Schema) -> Result<Vec<(String, String)>> {
+        let mut seen = Vec::new();
+        let mut definitions = Vec::new();
+        self.add_references(schema, &mut seen, &mut definitions)?;
+        Ok(definitions)
+    }
+
+    fn add_references(
+        &self,
+        schema: &Schema,
+        seen: &mut Vec<(String, SchemaVersion)>,
+        definitions: &mut Vec<(String, String)>,
+    ) -> Result<()> {
+        for reference in schema.references() {
+            let key = (reference.subject().to_owned(), reference.version());
+            if seen.contains(&key) {
+                continue;
+            }
+            seen.push(key);
+            let referenced = self
+                .client
+                .lookup_schema_by_version(reference.subject(), reference.version())
+                .with_context(|| {
+                    format!(
+                        "cannot look up version {} of subject {:?}, which is referenced as {:?}",
+                        reference.version().0,
+                        reference.subject(),
+                        reference.name()
+                    )
+                })?;
+            self.add_references(referenced.schema(), seen, definitions)?;
+            definitions.push((
+                reference.name().to_owned(),
+                referenced.schema().schema().to_owned(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A schema registry held in memory, for running the transform outside of Redpanda.
+///
+/// Schemas are given IDs in the order they are first registered, starting from 1, and each subject
+/// numbers its versions from 1 in the same way.
+#[derive(Debug, Default, Clone)]
+pub struct LocalRegistry {
+    subjects: Vec<SubjectSchema>,
+}
+
+impl LocalRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `schema` under `subject`, returning the version and ID it was registered with.
+    pub fn register(&mut self, subject: &str, schema: Schema) -> SubjectSchema {
+        if let Some(existing) = self
+            .subjects
+            .iter()
+            .find(|s| s.subject() == subject && *s.schema() == schema)
+        {
+            return existing.clone();
+        }
+        let id = match self.subjects.iter().find(|s| *s.schema() == schema) {
+            Some(existing) => existing.id(),
+            None => SchemaId(self.subjects.iter().map(|s| s.id().0).max().unwrap_or(0) + 1),
+        };
+        let version = self
+            .subjects
+            .iter()
+            .filter(|s| s.subject() == subject)
+            .count();
+        let registered = SubjectSchema::new(schema, subject, SchemaVersion(version as i32 + 1), id);
+        self.subjects.push(registered.clone());
+        registered
+    }
+}
+
+/// Lookups of schemas that aren't registered fail with a 404 error code, like the registry's API.
+const NOT_FOUND: i32 = 404;
+
+impl SchemaRegistryClientImpl for LocalRegistry {
+    fn lookup_schema_by_id(&self, id: SchemaId) -> Result<Schema, SchemaRegistryError> {
+        self.subjects
+            .iter()
+            .find(|s| s.id() == id)
+            .map(|s| s.schema().clone())
+            .ok_or(SchemaRegistryError::Unknown(NOT_FOUND))
+    }
+
+    fn lookup_schema_by_version(
+        &self,
+        subject: &str,
+        version: SchemaVersion,
+    ) -> Result<SubjectSchema, SchemaRegistryError> {
+        self.subjects
+            .iter()
+            .find(|s| s.subject() == subject && s.version() == version)
+            .cloned()
+            .ok_or(SchemaRegistryError::Unknown(NOT_FOUND))
+    }
+
+    fn lookup_latest_schema(&self, subject: &str) -> Result<SubjectSchema, SchemaRegistryError> {
+        self.subjects
+            .iter()
+            .filter(|s| s.subject() == subject)
+            .max_by_key(|s| s.version())
+            .cloned()
+            .ok_or(SchemaRegistryError::Unknown(NOT_FOUND))
+    }
+
+    fn create_schema(
+        &mut self,
+        subject: &str,
+        schema: Schema,
+    ) -> Result<SubjectSchema, SchemaRegistryError> {
+        Ok(self.register(subject, schema))
+    }
+}
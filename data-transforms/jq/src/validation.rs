@@ -0,0 +1,105 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Validation of the values the filter reads and writes with JSON Schema (draft 2020-12).
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use jaq_interpret::Val;
+use jsonschema::{Resource, ValidationError, Validator};
+
+/// At most this many violations are reported for a value, so that a large array of invalid items
+/// doesn't make an error message that is as large.
+const MAX_VIOLATIONS: usize = 10;
+
+/// A compiled JSON Schema.
+pub struct JsonSchema {
+    /// Where the schema is from, such as `INPUT_SCHEMA`, to report values that don't match it.
+    name: String,
+    validator: Validator,
+}
+
+impl JsonSchema {
+    /// Compile a schema, where `references` are the `(name, definition)` pairs of the schemas it
+    /// can reference by name with `$ref`.
+    pub fn parse(name: &str, definition: &str, references: &[(String, String)]) -> Result<Self> {
+        let schema: serde_json::Value =
+            serde_json::from_str(definition).context("schema is not valid JSON")?;
+        let mut options = jsonschema::draft202012::options();
+        for (reference, definition) in references {
+            let contents = serde_json::from_str(definition)
+                .with_context(|| format!("referenced schema {reference:?} is not valid JSON"))?;
+            options = options.with_resource(reference.clone(), Resource::from_contents(contents));
+        }
+        let validator = options.build(&schema).map_err(|e| anyhow!("{e}"))?;
+        Ok(Self {
+            name: name.to_owned(),
+            validator,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Check that `value` matches the schema, failing with the places where it doesn't.
+    pub fn validate(&self, value: &Val) -> Result<()> {
+        let instance = to_json(value);
+        let mut errors = self.validator.iter_errors(&instance);
+        let Some(first) = errors.next() else {
+            return Ok(());
+        };
+        let mut message = violation(&first);
+        let mut more = 0;
+        for (i, error) in errors.enumerate() {
+            if i + 1 < MAX_VIOLATIONS {
+                write!(message, "; {}", violation(&error))?;
+            } else {
+                more += 1;
+            }
+        }
+        if more > 0 {
+            write!(message, "; and {more} more")?;
+        }
+        Err(anyhow!(message))
+    }
+}
+
+/// Describe a violation along with the JSON pointer of the part of the value it's in.
+fn violation(error: &ValidationError) -> String {
+    match error.instance_path().as_str() {
+        "" => error.to_string(),
+        path => format!("{path}: {error}"),
+    }
+}
+
+/// Convert a value to JSON for the validator. Unlike the conversion of `jaq_interpret`, numbers
+/// that `serde_json` cannot represent are doubles instead of a panic.
+fn to_json(value: &Val) -> serde_json::Value {
+    use serde_json::Value;
+    match value {
+        Val::Null => Value::Null,
+        Val::Bool(b) => Value::Bool(*b),
+        Val::Int(i) => Value::from(*i),
+        Val::Float(f) => Value::from(*f),
+        Val::Num(n) => serde_json::Number::from_str(n)
+            .map(Value::Number)
+            .unwrap_or_else(|_| Value::from(n.parse::<f64>().unwrap_or(f64::NAN))),
+        Val::Str(s) => Value::String(s.to_string()),
+        Val::Arr(a) => Value::Array(a.iter().map(to_json).collect()),
+        Val::Obj(o) => Value::Object(o.iter().map(|(k, v)| (k.to_string(), to_json(v))).collect()),
+    }
+}
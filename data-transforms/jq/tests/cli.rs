@@ -0,0 +1,165 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests of the native runner, which runs the transform with [`jq::Transform::process`] on local
+//! files.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// Run the native runner with `args`, writing `stdin` to it.
+fn run(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_jq"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("cannot run jq");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "jq failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn runs_the_filter_on_values() {
+    let output = run(&["--var=FILTER=.id"], "{\"id\":1}\n\n{\"id\":2}\n");
+    assert_eq!(
+        stdout(&output),
+        "{\"headers\":{},\"key\":null,\"value\":\"1\"}\n\
+         {\"headers\":{},\"key\":null,\"value\":\"2\"}\n"
+    );
+}
+
+#[test]
+fn streams_stdin() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_jq"))
+        .arg("--var=FILTER=.id")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("cannot run jq");
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let (lines, received) = mpsc::channel();
+    thread::spawn(move || {
+        let mut line = String::new();
+        while stdout.read_line(&mut line).unwrap() > 0 {
+            lines.send(std::mem::take(&mut line)).unwrap();
+        }
+    });
+    for id in 1..=2 {
+        writeln!(stdin, "{{\"id\":{id}}}").unwrap();
+        stdin.flush().unwrap();
+        let line = received
+            .recv_timeout(Duration::from_secs(10))
+            .expect("no output before stdin was closed");
+        assert_eq!(
+            line,
+            format!("{{\"headers\":{{}},\"key\":null,\"value\":\"{id}\"}}\n")
+        );
+    }
+    drop(stdin);
+    assert!(child.wait().unwrap().success());
+}
+
+#[test]
+fn runs_the_filter_on_records() {
+    let output = run(
+        &[
+            "--var",
+            "FILTER=.key = \"k\"",
+            "--var=ENVELOPE=true",
+            "--records",
+            "-",
+        ],
+        "{\"key\": {\"base64\": \"/wA=\"}, \"value\": \"{}\", \"headers\": {\"a\": \"b\"}}\n",
+    );
+    assert_eq!(
+        stdout(&output),
+        "{\"headers\":{\"a\":\"b\"},\"key\":\"k\",\"value\":\"{}\"}\n"
+    );
+}
+
+#[test]
+fn reads_fixture_directories() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("cli-fixtures");
+    let _ = fs::remove_dir_all(&dir);
+    for (record, files) in [
+        ("1", &[("key", "user-1"), ("value", "{\"n\":1}")][..]),
+        (
+            "2",
+            &[("value", "{\"n\":2}"), ("headers.json", "{\"a\":\"b\"}")][..],
+        ),
+    ] {
+        let record = dir.join(record);
+        fs::create_dir_all(&record).unwrap();
+        for (name, contents) in files {
+            fs::write(record.join(name), contents).unwrap();
+        }
+    }
+    let output = run(&["--var=FILTER=.n + 1", dir.to_str().unwrap()], "");
+    assert_eq!(
+        stdout(&output),
+        "{\"headers\":{},\"key\":\"user-1\",\"value\":\"2\"}\n\
+         {\"headers\":{\"a\":\"b\"},\"key\":null,\"value\":\"3\"}\n"
+    );
+}
+
+#[test]
+fn applies_the_error_policy() {
+    let output = run(
+        &["--var=FILTER=.n", "--var=ERROR_POLICY=skip"],
+        "{\"n\":1}\nnot json\n{\"n\":3}\n",
+    );
+    assert_eq!(
+        stdout(&output),
+        "{\"headers\":{},\"key\":null,\"value\":\"1\"}\n\
+         {\"headers\":{},\"key\":null,\"value\":\"3\"}\n"
+    );
+}
+
+#[test]
+fn reports_the_failed_record() {
+    let output = run(&["--var=FILTER=.n"], "{\"n\":1}\nnot json\n");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("record 2 of stdin failed"), "{stderr}");
+}
+
+#[test]
+fn reports_invalid_filters() {
+    let output = run(&["--var=FILTER=.a |"], "");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(" --> FILTER:1:5"), "{stderr}");
+}
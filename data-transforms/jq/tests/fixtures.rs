@@ -0,0 +1,244 @@
+// Copyright 2024 Redpanda Data, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Table-driven tests of the transform that run without a broker.
+//!
+//! Each file in `tests/fixtures` holds an array of cases of the form:
+//!
+//! ```json
+//! {
+//!   "name": "deletes the email field",
+//!   "vars": {"FILTER": "del(.email)"},
+//!   "input": {"value": "{\"foo\":42,\"email\":\"help@example.com\"}"},
+//!   "output": [{"key": null, "value": "{\"foo\":42}", "headers": {}}]
+//! }
+//! ```
+//!
+//! `vars` are the transform's environment variables and records use the JSON representation of
+//! [`jq::record`]. Instead of `output`, a case can have an `error` which the transform must fail
+//! with, either when it starts or on the input record, for the case to pass. Each case runs through
+//! both [`Transform::apply`], as deployed transforms do, and [`Transform::process`], as the
+//! command-line tool does, which must write the same records.
+//!
+//! Cases with `topics`, such as `"topics": ["dlq"]`, fail writes to topics other than those and the
+//! output topic, like Redpanda does for topics that aren't output topics of the transform. They only
+//! run through [`Transform::apply`], as [`Transform::process`] doesn't write the records.
+//!
+//! Cases with `schemas` run with a schema registry holding them, registered in order so that the
+//! first has ID 1:
+//!
+//! ```json
+//! "schemas": [{"subject": "orders-value", "schema": {"type": "record", ...}, "references": []}]
+//! ```
+//!
+//! Schemas are Avro unless they have a `"schemaType"` of `"PROTOBUF"` or `"JSON"`, like in the
+//! registry's API, with optional references of the form `{"name": "Item", "subject": "items-value", "version": 1}`.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use jq::config::Config;
+use jq::record::{self, InputRecord};
+use jq::registry::LocalRegistry;
+use jq::{Output, Transform};
+use redpanda_transform_sdk::{
+    BorrowedHeader, BorrowedRecord, Record, RecordSink, RecordWriter, WriteError, WriteEvent,
+    WriteOptions,
+};
+use redpanda_transform_sdk_sr::{
+    Reference, Schema, SchemaFormat, SchemaRegistryClient, SchemaVersion,
+};
+use serde_json::Value;
+
+/// Captures the records written by the transform.
+#[derive(Default)]
+struct MockSink {
+    /// The topics other than the output topic that records can be written to, or `None` for any.
+    topics: Option<Vec<String>>,
+    written: Vec<Value>,
+}
+
+impl RecordSink for MockSink {
+    fn write(&mut self, r: BorrowedRecord<'_>, opts: WriteOptions<'_>) -> Result<(), WriteError> {
+        if let (Some(topics), Some(topic)) = (&self.topics, opts.topic) {
+            if !topics.iter().any(|t| t == topic) {
+                return Err(WriteError::Unknown(-1));
+            }
+        }
+        let output = Output {
+            topic: opts.topic.map(str::to_owned),
+            record: Record::new_with_headers(
+                r.key().map(<[u8]>::to_vec),
+                r.value().map(<[u8]>::to_vec),
+                r.headers().iter().map(BorrowedHeader::to_owned).collect(),
+            ),
+        };
+        self.written.push(record::output_to_json(&output));
+        Ok(())
+    }
+}
+
+/// Register the schemas of a case, in the form described above.
+fn registry(schemas: &Value) -> Result<LocalRegistry> {
+    let mut registry = LocalRegistry::new();
+    for schema in schemas.as_array().context("schemas must be an array")? {
+        let subject = schema["subject"]
+            .as_str()
+            .context("subject must be a string")?;
+        let definition = match &schema["schema"] {
+            Value::String(s) => s.clone(),
+            definition => definition.to_string(),
+        };
+        let references = match &schema["references"] {
+            Value::Null => Vec::new(),
+            references => references
+                .as_array()
+                .context("references must be an array")?
+                .iter()
+                .map(|r| {
+                    let version = r["version"]
+                        .as_i64()
+                        .context("version must be an integer")?;
+                    Ok(Reference::new(
+                        r["name"].as_str().context("name must be a string")?,
+                        r["subject"].as_str().context("subject must be a string")?,
+                        SchemaVersion(version as i32),
+                    ))
+                })
+                .collect::<Result<_>>()?,
+        };
+        let schema = match &schema["schemaType"] {
+            Value::Null => Schema::new_avro(definition, references),
+            Value::String(s) if s == "AVRO" => Schema::new_avro(definition, references),
+            Value::String(s) if s == "PROTOBUF" => Schema::new_protobuf(definition, references),
+            Value::String(s) if s == "JSON" => {
+                Schema::new(definition, SchemaFormat::Json, references)
+            }
+            other => bail!("schemaType must be AVRO, PROTOBUF or JSON, got {other}"),
+        };
+        registry.register(subject, schema);
+    }
+    Ok(registry)
+}
+
+/// Run a case, returning what the transform wrote or the error it failed with.
+fn run(vars: &Value, schemas: &Value, topics: &Value, input: &Value) -> Result<Vec<Value>> {
+    let vars = vars
+        .as_object()
+        .context("vars must be an object")?
+        .iter()
+        .map(|(name, value)| match value {
+            Value::String(s) => Ok((name.clone(), s.clone())),
+            _ => bail!("var {name} must be a string"),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let input = InputRecord::from_json(input.clone()).context("invalid input record")?;
+    let config = Config::from_vars(vars)?;
+    let transform = match schemas {
+        Value::Null => Transform::new(config)?,
+        schemas => {
+            let registry = SchemaRegistryClient::new_wrapping(Box::new(registry(schemas)?));
+            Transform::with_registry(config, registry)?
+        }
+    };
+    let topics = match topics {
+        Value::Null => None,
+        topics => Some(serde_json::from_value(topics.clone()).context("invalid topics")?),
+    };
+    let mut sink = MockSink {
+        topics,
+        written: Vec::new(),
+    };
+    let event = WriteEvent {
+        record: input.as_written(),
+    };
+    let applied = transform.apply(event, &mut RecordWriter::new(&mut sink));
+    // Only `apply` writes records, so writes that fail cannot be compared with `process`.
+    if sink.topics.is_some() {
+        return applied.map(|()| sink.written);
+    }
+    // The command-line tool runs the transform with `process`, which must write the same records.
+    let processed = transform.process(&input.as_written());
+    match (applied, processed) {
+        (Ok(()), Ok(outputs)) => {
+            let processed: Vec<_> = outputs.iter().map(record::output_to_json).collect();
+            if processed != sink.written {
+                bail!(
+                    "process wrote {}\nbut apply wrote {}",
+                    Value::Array(processed),
+                    Value::Array(sink.written)
+                );
+            }
+            Ok(sink.written)
+        }
+        (Err(e), Err(_)) => Err(e),
+        (Ok(()), Err(e)) => bail!("process failed with {e:#} but apply succeeded"),
+        (Err(e), Ok(_)) => bail!("apply failed with {e:#} but process succeeded"),
+    }
+}
+
+fn check(case: &Value) -> Result<()> {
+    let result = run(
+        &case["vars"],
+        &case["schemas"],
+        &case["topics"],
+        &case["input"],
+    );
+    match (case.get("output"), case.get("error")) {
+        (Some(expected), None) => {
+            let written = Value::Array(result.map_err(|e| anyhow::anyhow!("failed: {e:#}"))?);
+            if written != *expected {
+                bail!("wrote {written}\nexpected {expected}");
+            }
+        }
+        (None, Some(Value::String(expected))) => match result {
+            Ok(written) => bail!(
+                "wrote {}\nexpected error {expected:?}",
+                Value::Array(written)
+            ),
+            Err(e) if !format!("{e:#}").contains(expected) => {
+                bail!("failed with {e:#}\nexpected error {expected:?}")
+            }
+            Err(_) => {}
+        },
+        _ => bail!("case must have either an output or an error"),
+    }
+    Ok(())
+}
+
+#[test]
+fn fixtures() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .expect("cannot read fixtures")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no fixtures in {}", dir.display());
+    let mut failures = Vec::new();
+    for path in &paths {
+        let file = path.file_name().unwrap().to_string_lossy();
+        let cases: Vec<Value> = serde_json::from_slice(&fs::read(path).unwrap())
+            .unwrap_or_else(|e| panic!("{file} is not a JSON array: {e}"));
+        for case in &cases {
+            let name = case["name"].as_str().unwrap_or("unnamed");
+            if let Err(e) = check(case) {
+                failures.push(format!("{file}: {name}: {e:#}"));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "\n{}\n", failures.join("\n\n"));
+}